@@ -0,0 +1,78 @@
+
+export interface DiagnosticPosition {
+    line: number;
+    column: number;
+}
+
+export interface CompileDiagnostic {
+    severity: "error" | "warning";
+    message: string;
+    hints: string[];
+    path: string | null;
+    start: DiagnosticPosition | null;
+    end: DiagnosticPosition | null;
+}
+
+/// `setupDiagnostics` drives the overlay that shows the errors of a failed
+/// compilation on top of the last good render.
+export function setupDiagnostics() {
+    const overlay = document.getElementById("typst-diagnostics");
+    const listElem = document.getElementById("typst-diagnostics-list");
+    const closeElem = document.getElementById("typst-diagnostics-close");
+    const appElem = document.getElementById("typst-app");
+
+    const hide = () => {
+        overlay?.classList.add("hidden");
+    };
+    closeElem?.addEventListener("click", hide);
+
+    const locationOf = (diag: CompileDiagnostic) => {
+        if (!diag.path) {
+            return "";
+        }
+        if (!diag.start) {
+            return diag.path;
+        }
+        /// Positions are 0-based, while editors count from 1.
+        return `${diag.path}:${diag.start.line + 1}:${diag.start.column + 1}`;
+    };
+
+    return {
+        show(diagnostics: CompileDiagnostic[]) {
+            if (listElem) {
+                listElem.replaceChildren(...diagnostics.map(diag => {
+                    const item = document.createElement("li");
+                    item.className = `typst-diagnostic ${diag.severity}`;
+
+                    const location = document.createElement("div");
+                    location.className = "typst-diagnostic-location";
+                    location.textContent = locationOf(diag);
+
+                    const message = document.createElement("div");
+                    message.className = "typst-diagnostic-message";
+                    message.textContent = `${diag.severity}: ${diag.message}`;
+
+                    item.append(location, message);
+                    for (const hint of diag.hints) {
+                        const hintElem = document.createElement("div");
+                        hintElem.className = "typst-diagnostic-hint";
+                        hintElem.textContent = `hint: ${hint}`;
+                        item.append(hintElem);
+                    }
+                    return item;
+                }));
+            }
+            overlay?.classList.remove("hidden");
+            appElem?.classList.add("stale");
+        },
+        clear() {
+            hide();
+            listElem?.replaceChildren();
+            appElem?.classList.remove("stale");
+        },
+        dispose() {
+            this.clear();
+            closeElem?.removeEventListener("click", hide);
+        },
+    };
+}
@@ -0,0 +1,141 @@
+/// The area of a page in the window, and its size in the document.
+interface PageRect {
+    left: number;
+    top: number;
+    /// The scale from the document to the window.
+    scale: number;
+    width: number;
+    height: number;
+}
+
+/// The pages of the document with their areas in the window, in order.
+function pageRects(docRoot: Element): PageRect[] {
+    const rects: PageRect[] = [];
+    if (docRoot.getAttribute("data-render-mode") === "canvas") {
+        for (const page of docRoot.querySelectorAll(".typst-page")) {
+            const container = page.firstElementChild;
+            if (!container) {
+                continue;
+            }
+            const width = Number.parseFloat(container.getAttribute("data-page-width") || "0") || 0;
+            const height = Number.parseFloat(container.getAttribute("data-page-height") || "0") || 0;
+            const appliedScale = Number.parseFloat(container.getAttribute("data-applied-scale") || "1") || 1;
+            const rect = container.getBoundingClientRect();
+            if (width > 0) {
+                rects.push({ left: rect.left, top: rect.top, scale: rect.width / appliedScale / width, width, height });
+            }
+        }
+        return rects;
+    }
+
+    const dataWidth = Number.parseFloat(docRoot.getAttribute("data-width") || "0") || 0;
+    if (dataWidth <= 0) {
+        return rects;
+    }
+    const docRect = docRoot.getBoundingClientRect();
+    const scale = docRect.width / dataWidth;
+    for (const page of docRoot.children) {
+        if (page.tagName !== "g" || !page.classList.contains("typst-page")) {
+            continue;
+        }
+        const transform = (page as SVGGElement).transform.baseVal.consolidate()?.matrix;
+        rects.push({
+            left: docRect.left + (transform?.e || 0) * scale,
+            top: docRect.top + (transform?.f || 0) * scale,
+            scale,
+            width: Number.parseFloat(page.getAttribute("data-page-width") || "0") || 0,
+            height: Number.parseFloat(page.getAttribute("data-page-height") || "0") || 0,
+        });
+    }
+    return rects;
+}
+
+/// The region of the document shown in the window, as the top left and the
+/// bottom right positions, each one as `<page> <x> <y>`.
+function visibleRegion(docRoot: Element): string | undefined {
+    const pages = pageRects(docRoot);
+    if (pages.length === 0) {
+        return undefined;
+    }
+    const position = (i: number, clientX: number, clientY: number) => {
+        const page = pages[i];
+        const x = (clientX - page.left) / page.scale;
+        const y = Math.min(Math.max((clientY - page.top) / page.scale, 0), page.height);
+        return `${i + 1} ${x} ${y}`;
+    };
+
+    // the first page ending below the top of the window
+    let first = pages.findIndex(page => page.top + page.height * page.scale > 0);
+    if (first < 0) {
+        first = pages.length - 1;
+    }
+    // the last page starting above the bottom of the window
+    let last = first;
+    while (last + 1 < pages.length && pages[last + 1].top < window.innerHeight) {
+        last++;
+    }
+    return `${position(first, 0, 0)},${position(last, window.innerWidth, window.innerHeight)}`;
+}
+
+/// `setupFollowCursor` drives the follow-cursor mode, in which the server
+/// scrolls the preview to the cursor of the editor as soon as the cursor
+/// leaves the visible region.
+///
+/// The mode is shared with the editor and the other webviews, and toggled by
+/// the toolbar button or the c key. While it is on, the visible region is
+/// reported to the server as it changes.
+export function setupFollowCursor(send: (msg: string) => void, reportRegion: boolean) {
+    const button = document.getElementById("typst-top-follow-button");
+    const appElem = document.getElementById("typst-app");
+
+    let enabled = false;
+    let pending: ReturnType<typeof setTimeout> | undefined = undefined;
+
+    const report = () => {
+        pending = undefined;
+        const docRoot = appElem?.querySelector(".typst-doc");
+        const region = docRoot && visibleRegion(docRoot);
+        if (enabled && reportRegion && region) {
+            send(`visible,${region}`);
+        }
+    };
+    const onViewportChange = () => {
+        if (enabled && pending === undefined) {
+            pending = setTimeout(report, 100);
+        }
+    };
+
+    const toggle = () => send(`follow-cursor,${!enabled}`);
+    const onKey = (e: KeyboardEvent) => {
+        if (e.key !== "c" || e.ctrlKey || e.metaKey || e.altKey || e.target instanceof HTMLInputElement) {
+            return;
+        }
+        toggle();
+        e.preventDefault();
+    };
+
+    button?.addEventListener("click", toggle);
+    window.addEventListener("keydown", onKey);
+    window.addEventListener("scroll", onViewportChange);
+    window.addEventListener("resize", onViewportChange);
+
+    return {
+        /// Shows the mode, as changed by any party.
+        setEnabled(value: boolean) {
+            enabled = value;
+            button?.classList.toggle("active", enabled);
+            if (enabled) {
+                report();
+            }
+        },
+        /// Reports the region anew, as the pages are laid out anew.
+        refresh: onViewportChange,
+        dispose() {
+            clearTimeout(pending);
+            button?.removeEventListener("click", toggle);
+            window.removeEventListener("keydown", onKey);
+            window.removeEventListener("scroll", onViewportChange);
+            window.removeEventListener("resize", onViewportChange);
+        },
+    };
+}
@@ -6,6 +6,9 @@ import "./styles/toolbar.css";
 import "./styles/layout.css";
 import "./styles/help-panel.css";
 import "./styles/outline.css";
+import "./styles/presenter.css";
+import "./styles/diagnostics.css";
+import "./styles/search.css";
 
 import { wsMain, PreviewMode } from './ws';
 import { setupDrag } from './drag';
@@ -39,8 +42,15 @@ function retrieveWsArgs() {
     /// Also, it is the default url to connect to.
     let url = "ws://127.0.0.1:23625";
 
+    /// The string `preview-arg:role:Audience` is a placeholder
+    /// It will be replaced by `Presenter` for the presenter view.
+    let role = 'preview-arg:role:Audience';
+    /// Remove the placeholder prefix.
+    role = role.replace('preview-arg:role:', '');
+    let isPresenter = role === 'Presenter';
+
     /// Return a `WsArgs` object.
-    return { url, previewMode, isContentPreview: false };
+    return { url, previewMode, isContentPreview: false, isPresenter };
 }
 
 /// `buildWs` returns a object, which keeps track of websocket
@@ -63,7 +73,7 @@ function buildWs() {
 
     return { nextWs };
 
-    function resetAppMode({ previewMode: mode, isContentPreview }) {
+    function resetAppMode({ previewMode: mode, isContentPreview, isPresenter }) {
         const app = document.getElementById('typst-container');
 
         /// Set the root css selector to the content preview mode.
@@ -72,6 +82,12 @@ function buildWs() {
             app.classList.add('content-preview');
         }
 
+        /// Set the root css selector to the presenter view.
+        app.classList.remove('presenter');
+        if (isPresenter && mode === PreviewMode.Slide) {
+            app.classList.add('presenter');
+        }
+
         /// Set the root css selector to the preview mode.
         app.classList.remove('mode-slide');
         app.classList.remove('mode-doc');
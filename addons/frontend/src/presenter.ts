@@ -0,0 +1,108 @@
+
+export interface SpeakerNote {
+    page_no: number;
+    note: string;
+}
+
+export interface SpeakerNotes {
+    pages: number;
+    notes: SpeakerNote[];
+}
+
+/// `setupPresenter` drives the presenter panel, which shows the timer, the
+/// speaker notes of the current slide, and the next slide with its notes.
+///
+/// The next slide is rendered by the `/page/<n>.svg` endpoint of the static
+/// host. Without it, only the number of the next slide is shown.
+export function setupPresenter() {
+    const panel = document.getElementById("typst-presenter-panel");
+    const timerElem = document.getElementById("typst-presenter-timer");
+    const slideElem = document.getElementById("typst-presenter-slide");
+    const notesElem = document.getElementById("typst-presenter-notes");
+    const nextElem = document.getElementById("typst-presenter-next");
+    const nextNotesElem = document.getElementById("typst-presenter-next-notes");
+    const nextThumbnailElem = document.getElementById("typst-presenter-next-thumbnail") as HTMLImageElement | null;
+    /// Thumbnails are rendered by the static host, which is absent in editor webviews.
+    const canFetchThumbnail = location.protocol.startsWith("http");
+    /// A slide which cannot be rendered is left out, rather than shown broken.
+    let brokenThumbnail: string | null = null;
+    const onThumbnailError = () => {
+        brokenThumbnail = nextThumbnailElem?.getAttribute("src") ?? null;
+        nextThumbnailElem?.classList.add("hidden");
+    };
+    nextThumbnailElem?.addEventListener("error", onThumbnailError);
+    panel?.classList.remove("hidden");
+
+    let speakerNotes: SpeakerNotes = { pages: 0, notes: [] };
+    let currentPage = 1;
+    /// Bumped on each compilation to invalidate the thumbnail.
+    let documentVersion = 0;
+
+    let startTime = Date.now();
+    const formatElapsed = () => {
+        const elapsed = Math.floor((Date.now() - startTime) / 1000);
+        const minutes = Math.floor(elapsed / 60).toString().padStart(2, "0");
+        const seconds = (elapsed % 60).toString().padStart(2, "0");
+        return `${minutes}:${seconds}`;
+    };
+    const tickTimer = () => {
+        if (timerElem) {
+            timerElem.textContent = formatElapsed();
+        }
+    };
+    const resetTimer = () => {
+        startTime = Date.now();
+        tickTimer();
+    };
+    timerElem?.addEventListener("click", resetTimer);
+    const timer = setInterval(tickTimer, 1000);
+    tickTimer();
+
+    const notesOf = (page: number) => speakerNotes.notes
+        .filter(n => n.page_no === page)
+        .map(n => n.note)
+        .join("\n\n");
+
+    const render = () => {
+        const pages = speakerNotes.pages;
+        if (slideElem) {
+            slideElem.textContent = pages > 0 ? `${currentPage} / ${pages}` : `${currentPage}`;
+        }
+        if (notesElem) {
+            notesElem.textContent = notesOf(currentPage);
+        }
+        const hasNext = pages === 0 || currentPage < pages;
+        if (nextElem) {
+            nextElem.textContent = hasNext ? `Slide ${currentPage + 1}` : "End of slides";
+        }
+        if (nextNotesElem) {
+            nextNotesElem.textContent = hasNext ? notesOf(currentPage + 1) : "";
+        }
+        if (nextThumbnailElem && canFetchThumbnail) {
+            const src = `/page/${currentPage + 1}.svg?v=${documentVersion}`;
+            if (hasNext && nextThumbnailElem.getAttribute("src") !== src) {
+                nextThumbnailElem.src = src;
+            }
+            nextThumbnailElem.classList.toggle("hidden", !hasNext || brokenThumbnail === src);
+        }
+    };
+    render();
+
+    return {
+        setNotes(notes: SpeakerNotes) {
+            speakerNotes = notes;
+            documentVersion += 1;
+            render();
+        },
+        setPage(page: number) {
+            currentPage = page;
+            render();
+        },
+        dispose() {
+            clearInterval(timer);
+            timerElem?.removeEventListener("click", resetTimer);
+            nextThumbnailElem?.removeEventListener("error", onThumbnailError);
+            panel?.classList.add("hidden");
+        },
+    };
+}
@@ -0,0 +1,72 @@
+import { collectElementPath } from "typst-dom/typst-debug-info.mjs";
+
+/// The text shown first in the window, that is the topmost text of the first
+/// page reaching into the window.
+function topText(appElem: Element): Element | undefined {
+    const page = Array.from(appElem.querySelectorAll(".typst-page"))
+        .find(page => page.getBoundingClientRect().bottom > 0);
+    if (!page) {
+        return undefined;
+    }
+    let best: { elem: Element; top: number; left: number } | undefined = undefined;
+    for (const elem of page.querySelectorAll(".typst-text")) {
+        const rect = elem.getBoundingClientRect();
+        if (rect.bottom <= 0 || rect.top >= window.innerHeight) {
+            continue;
+        }
+        if (!best || rect.top < best.top || (rect.top === best.top && rect.left < best.left)) {
+            best = { elem, top: rect.top, left: rect.left };
+        }
+    }
+    return best?.elem;
+}
+
+/// `setupScrollReveal` reports the text at the top of the window to the
+/// server as the preview is scrolled, which has the editor reveal its source.
+///
+/// The scrolls made on behalf of the editor are not reported back, so that
+/// the editor is not moved by its own jumps.
+export function setupScrollReveal(send: (msg: string) => void, enabled: boolean) {
+    const appElem = document.getElementById("typst-app");
+
+    let pending: ReturnType<typeof setTimeout> | undefined = undefined;
+    /// The scrolls are left out until then, as they may be smooth.
+    let ignoreUntil = 0;
+    let lastPath: string | undefined = undefined;
+
+    const report = () => {
+        pending = undefined;
+        if (performance.now() < ignoreUntil) {
+            return;
+        }
+        const elem = appElem && topText(appElem);
+        const path = appElem && elem && collectElementPath(appElem, elem);
+        if (!path) {
+            return;
+        }
+        const json = JSON.stringify(path);
+        if (json !== lastPath) {
+            lastPath = json;
+            send(`top-element,${json}`);
+        }
+    };
+    const onScroll = () => {
+        clearTimeout(pending);
+        pending = setTimeout(report, 150);
+    };
+
+    if (enabled) {
+        window.addEventListener("scroll", onScroll);
+    }
+
+    return {
+        /// Leaves out the scroll about to be made on behalf of the editor.
+        ignoreScroll() {
+            ignoreUntil = performance.now() + 1000;
+        },
+        dispose() {
+            clearTimeout(pending);
+            window.removeEventListener("scroll", onScroll);
+        },
+    };
+}
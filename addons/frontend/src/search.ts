@@ -0,0 +1,203 @@
+export interface SearchPosition {
+    page_no: number;
+    x: number;
+    y: number;
+}
+
+export interface SearchRange {
+    start: SearchPosition;
+    end: SearchPosition;
+}
+
+export interface SearchHit {
+    span: string | null;
+    ranges: SearchRange[];
+}
+
+export interface SearchResult {
+    query: string;
+    hits: SearchHit[];
+    truncated: boolean;
+}
+
+const SVG_NS = "http://www.w3.org/2000/svg";
+
+/// `setupSearch` drives the search bar, which highlights the hits of a search
+/// of the document text and steps through them.
+///
+/// Enter and Shift+Enter step to the next and the previous hit, while
+/// Ctrl+Enter reveals the current hit in the source.
+export function setupSearch(send: (msg: string) => void) {
+    const bar = document.getElementById("typst-search");
+    const input = document.getElementById("typst-search-input") as HTMLInputElement | null;
+    const countElem = document.getElementById("typst-search-count");
+    const prevElem = document.getElementById("typst-search-prev");
+    const nextElem = document.getElementById("typst-search-next");
+    const closeElem = document.getElementById("typst-search-close");
+    const appElem = document.getElementById("typst-app");
+
+    let result: SearchResult = { query: "", hits: [], truncated: false };
+    let current = -1;
+    let pending: ReturnType<typeof setTimeout> | undefined = undefined;
+
+    const request = () => {
+        const query = input?.value.trim() || "";
+        if (!query) {
+            result = { query: "", hits: [], truncated: false };
+            current = -1;
+            render();
+            return;
+        }
+        send(`search,${JSON.stringify({ query })}`);
+    };
+    const onInput = () => {
+        clearTimeout(pending);
+        pending = setTimeout(request, 200);
+    };
+
+    /// The page groups of the svg document, by page number.
+    const pageOf = (pageNo: number) => {
+        const docRoot = appElem?.querySelector(".typst-doc");
+        if (!docRoot || docRoot.getAttribute("data-render-mode") === "canvas") {
+            return undefined;
+        }
+        let nthPage = 0;
+        for (const child of docRoot.children) {
+            if (child.tagName === "g" && ++nthPage === pageNo) {
+                return child;
+            }
+        }
+        return undefined;
+    };
+
+    const clearHighlights = () => {
+        appElem?.querySelectorAll(".typst-search-hit").forEach(elem => elem.remove());
+    };
+
+    const highlight = () => {
+        clearHighlights();
+        result.hits.forEach((hit, i) => {
+            for (const range of hit.ranges) {
+                const page = pageOf(range.start.page_no);
+                if (!page) {
+                    continue;
+                }
+                const rect = document.createElementNS(SVG_NS, "rect");
+                rect.setAttribute("class", i === current ? "typst-search-hit current" : "typst-search-hit");
+                rect.setAttribute("x", range.start.x.toString());
+                rect.setAttribute("y", range.start.y.toString());
+                rect.setAttribute("width", (range.end.x - range.start.x).toString());
+                rect.setAttribute("height", (range.end.y - range.start.y).toString());
+                page.append(rect);
+            }
+        });
+    };
+
+    const render = () => {
+        if (countElem) {
+            const total = `${result.hits.length}${result.truncated ? "+" : ""}`;
+            countElem.textContent = result.query ? `${current + 1} / ${total}` : "";
+        }
+        highlight();
+    };
+
+    const step = (delta: number) => {
+        const count = result.hits.length;
+        if (count === 0) {
+            return;
+        }
+        current = (current + delta + count) % count;
+        render();
+        const { start } = result.hits[current].ranges[0];
+        const rootElem = appElem?.firstElementChild;
+        if (rootElem) {
+            window.handleTypstLocation(rootElem, start.page_no, start.x, start.y);
+        }
+    };
+
+    const revealSource = () => {
+        const hit = result.hits[current];
+        if (hit?.span) {
+            send(`srclocation ${hit.span}`);
+        }
+    };
+
+    const open = () => {
+        bar?.classList.remove("hidden");
+        input?.focus();
+        input?.select();
+    };
+    const close = () => {
+        bar?.classList.add("hidden");
+        clearHighlights();
+        input?.blur();
+    };
+
+    const onInputKey = (e: KeyboardEvent) => {
+        if (e.key === "Enter") {
+            if (e.ctrlKey || e.metaKey) {
+                revealSource();
+            } else {
+                step(e.shiftKey ? -1 : 1);
+            }
+        } else if (e.key === "Escape") {
+            close();
+        } else {
+            // the keys typed in the search bar are not key bindings
+            e.stopPropagation();
+            return;
+        }
+        e.preventDefault();
+        e.stopPropagation();
+    };
+    const onWindowKey = (e: KeyboardEvent) => {
+        if ((e.ctrlKey || e.metaKey) && e.key === "f") {
+            e.preventDefault();
+            open();
+        }
+    };
+    const onPrev = () => step(-1);
+    const onNext = () => step(1);
+
+    input?.addEventListener("input", onInput);
+    input?.addEventListener("keydown", onInputKey);
+    prevElem?.addEventListener("click", onPrev);
+    nextElem?.addEventListener("click", onNext);
+    closeElem?.addEventListener("click", close);
+    window.addEventListener("keydown", onWindowKey);
+
+    return {
+        show(res: SearchResult) {
+            /// Drops the results of outdated queries.
+            if (res.query !== input?.value.trim()) {
+                return;
+            }
+            /// A refresh keeps the current hit in place.
+            const refreshed = res.query === result.query && current < res.hits.length;
+            result = res;
+            if (refreshed) {
+                render();
+                return;
+            }
+            current = -1;
+            render();
+            step(1);
+        },
+        /// Searches again, as the hits of the last search are laid out anew.
+        refresh() {
+            if (!bar?.classList.contains("hidden") && result.query) {
+                request();
+            }
+        },
+        dispose() {
+            clearTimeout(pending);
+            close();
+            input?.removeEventListener("input", onInput);
+            input?.removeEventListener("keydown", onInputKey);
+            prevElem?.removeEventListener("click", onPrev);
+            nextElem?.removeEventListener("click", onNext);
+            closeElem?.removeEventListener("click", close);
+            window.removeEventListener("keydown", onWindowKey);
+        },
+    };
+}
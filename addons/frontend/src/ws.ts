@@ -9,7 +9,12 @@ import renderModule from "@myriaddreamin/typst-ts-renderer/pkg/typst_ts_renderer
 // import { RenderSession as RenderSession2 } from "@myriaddreamin/typst-ts-renderer/pkg/wasm-pack-shim.mjs";
 import { RenderSession } from "@myriaddreamin/typst.ts/dist/esm/renderer.mjs";
 import { WebSocketSubject, webSocket } from 'rxjs/webSocket';
-import { Subject, Subscription, buffer, debounceTime, fromEvent, tap } from "rxjs";
+import { Subject, Subscription, buffer, concatMap, debounceTime, fromEvent, of, tap } from "rxjs";
+import { setupPresenter } from "./presenter";
+import { setupDiagnostics } from "./diagnostics";
+import { setupSearch } from "./search";
+import { setupFollowCursor } from "./follow";
+import { setupScrollReveal } from "./reveal";
 export { PreviewMode } from 'typst-dom/typst-doc.mjs';
 
 // for debug propose
@@ -21,13 +26,32 @@ const enc = new TextEncoder();
 const dec = new TextDecoder();
 const NOT_AVAIABLE = "current not avalible";
 const COMMA = enc.encode(",");
+// asks the server to compress render payloads as `deflate,<zlib stream>`
+const DEFLATE_PROTOCOL = "typst-preview.deflate";
+const DEFLATE_PREFIX = enc.encode("deflate,");
+
+function isDeflated(data: ArrayBuffer) {
+    if (!(data instanceof ArrayBuffer) || data.byteLength < DEFLATE_PREFIX.length) {
+        return false;
+    }
+    const prefix = new Uint8Array(data, 0, DEFLATE_PREFIX.length);
+    return prefix.every((byte, i) => byte === DEFLATE_PREFIX[i]);
+}
+
+function inflate(data: ArrayBuffer): Promise<ArrayBuffer> {
+    const stream = new Blob([data.slice(DEFLATE_PREFIX.length)])
+        .stream()
+        .pipeThrough(new DecompressionStream("deflate"));
+    return new Response(stream).arrayBuffer();
+}
 export interface WsArgs {
     url: string;
     previewMode: PreviewMode;
     isContentPreview: boolean;
+    isPresenter?: boolean;
 }
 
-export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
+export async function wsMain({ url, previewMode, isContentPreview, isPresenter }: WsArgs) {
     if (!url) {
         const hookedElem = document.getElementById("typst-app");
         if (hookedElem) {
@@ -39,6 +63,95 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
     let disposed = false;
     let $ws: WebSocketSubject<ArrayBuffer> | undefined = undefined;
     const subsribes: Subscription[] = [];
+    const presenter = (isPresenter && previewMode === PreviewMode.Slide) ? setupPresenter() : undefined;
+    const diagnostics = setupDiagnostics();
+    const search = setupSearch(msg => window.typstWebsocket?.send(msg));
+    // a slide is always shown whole, so that the server scrolls to any cursor
+    // off the slide
+    const follow = setupFollowCursor(
+        msg => window.typstWebsocket?.send(msg),
+        previewMode !== PreviewMode.Slide && !isContentPreview,
+    );
+    const reveal = setupScrollReveal(
+        msg => window.typstWebsocket?.send(msg),
+        previewMode !== PreviewMode.Slide && !isContentPreview,
+    );
+
+    /// Whether this webview drives the slides of all following webviews.
+    /// The presenter view always leads the audience views.
+    let leadingSlides = !!presenter;
+    /// Whether this webview follows the slide changes of the leader.
+    let followingSlides = !presenter;
+    /// The page sent by the leader before the document is ready.
+    let pendingLeaderPage: number | undefined = undefined;
+    /// The render session of the server, resumed after reconnecting.
+    let sessionToken: string | undefined = undefined;
+    /// The version of the last render frame applied to the document.
+    let docVersion = 0;
+    /// The plain text of the current selection, as laid out by the server.
+    let selectedText: string | undefined = undefined;
+
+    /// Copies the text of the selection, rather than the one of the svg.
+    const clearSelectedText = () => {
+        selectedText = undefined;
+    };
+    const copySelectedText = (e: ClipboardEvent) => {
+        if (selectedText === undefined || window.getSelection()?.isCollapsed !== false) {
+            return;
+        }
+        e.clipboardData?.setData("text/plain", selectedText);
+        e.preventDefault();
+    };
+    document.addEventListener("mousedown", clearSelectedText);
+    document.addEventListener("copy", copySelectedText);
+
+    /// The positions of the last jump from the source, when it is shown more
+    /// than once.
+    let occurrences: { svgDoc: TypstDocument; positions: number[][]; current: number } | undefined = undefined;
+
+    /// Steps through the occurrences with n and Shift+N.
+    const cycleOccurrences = (e: KeyboardEvent) => {
+        if (!occurrences || e.ctrlKey || e.metaKey || e.altKey || (e.key !== "n" && e.key !== "N")) {
+            return;
+        }
+        if (e.target instanceof HTMLInputElement) {
+            return;
+        }
+        const count = occurrences.positions.length;
+        occurrences.current = (occurrences.current + (e.shiftKey ? count - 1 : 1)) % count;
+        jumpTo(occurrences.svgDoc, occurrences.positions[occurrences.current]);
+        e.preventDefault();
+    };
+    window.addEventListener("keydown", cycleOccurrences);
+
+    function jumpTo(svgDoc: TypstDocument, [page, x, y]: number[]) {
+        if (previewMode === PreviewMode.Slide) {
+            const pageSelector = document.getElementById("typst-page-selector") as HTMLSelectElement | undefined;
+            if (svgDoc.setPartialPageNumber(page)) {
+                if (pageSelector) {
+                    pageSelector.value = page.toString();
+                }
+                // todo: hint location
+            }
+            return;
+        }
+
+        const rootElem =
+            document.getElementById("typst-app")?.firstElementChild;
+        if (rootElem) {
+            /// Note: when it is really scrolled, it will trigger `svgDoc.addViewportChange`
+            /// via `window.onscroll` event
+            window.handleTypstLocation(rootElem, page, x, y);
+        }
+    }
+
+    /// Reports the slide shown by the leader, so that the followers flip together.
+    function onSlideChanged(page: number) {
+        presenter?.setPage(page);
+        if (leadingSlides) {
+            window.typstWebsocket?.send(`slide-change,${page}`);
+        }
+    }
 
     function createSvgDocument(kModule: RenderSession) {
         const hookedElem = document.getElementById("typst-app")!;
@@ -102,7 +215,9 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
                             return;
                         }
                         const page = Number.parseInt(inpPageSelector.value);
-                        svgDoc.setPartialPageNumber(page);
+                        if (svgDoc.setPartialPageNumber(page)) {
+                            onSlideChanged(page);
+                        }
                     });
                 }
             }
@@ -137,6 +252,7 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
                     if (svgDoc.setPartialPageNumber(page)) {
                         pageSelector.value = page.toString();
                         blurInput();
+                        onSlideChanged(page);
                     }
                 }
             }
@@ -195,6 +311,16 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
                         removeHelp();
                         focusInput();
                         break;
+                    case "l":
+                        leadingSlides = !leadingSlides;
+                        console.log("leading slides", leadingSlides);
+                        window.typstWebsocket?.send(`slide-lead,${leadingSlides}`);
+                        onSlideChanged(svgDoc.getPartialPageNumber());
+                        break;
+                    case "f":
+                        followingSlides = !followingSlides;
+                        console.log("following slides", followingSlides);
+                        break;
                     case "Escape":
                         removeHelp();
                         blurInput();
@@ -220,13 +346,24 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
             binaryType: "arraybuffer",
             serializer: t => t,
             deserializer: (event) => event.data,
+            protocol: typeof DecompressionStream !== "undefined" ? DEFLATE_PROTOCOL : undefined,
             openObserver: {
                 next: (e) => {
                     const sock = e.target;
                     console.log('WebSocket connection opened', sock);
                     window.typstWebsocket = sock as any;
-                    svgDoc.reset();
-                    window.typstWebsocket.send("current");
+                    if (sessionToken !== undefined) {
+                        // the server replays the missed frames, or starts over
+                        // with a snapshot
+                        window.typstWebsocket.send(`resume,${sessionToken} ${docVersion}`);
+                    } else {
+                        svgDoc.reset();
+                        window.typstWebsocket.send("current");
+                    }
+                    if (leadingSlides) {
+                        window.typstWebsocket.send("slide-lead,true");
+                        onSlideChanged(svgDoc.getPartialPageNumber());
+                    }
                 }
             },
             closeObserver: {
@@ -245,6 +382,14 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
         const dispose = () => {
             disposed = true;
             svgDoc.dispose();
+            presenter?.dispose();
+            diagnostics.dispose();
+            search.dispose();
+            follow.dispose();
+            reveal.dispose();
+            document.removeEventListener("mousedown", clearSelectedText);
+            document.removeEventListener("copy", copySelectedText);
+            window.removeEventListener("keydown", cycleOccurrences);
             for (const sub of subsribes.splice(0, subsribes.length)) {
                 sub.unsubscribe();
             }
@@ -254,7 +399,8 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
         // window.typstWebsocket = new WebSocket("ws://127.0.0.1:23625");
 
 
-        $ws.subscribe({
+        // inflates the payloads in order
+        $ws.pipe(concatMap(data => isDeflated(data) ? inflate(data) : of(data))).subscribe({
             next: (data) => batchMessageChannel.next(data), // Called whenever there is a message from the server.
             error: err => console.log("WebSocket Error: ", err), // Called if at any point WebSocket API signals some kind of error.
             complete: () => console.log('complete') // Called when connection is closed (for whatever reason).
@@ -306,34 +452,17 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
 
             if (message[0] === "jump" || message[0] === "viewport") {
                 // todo: aware height padding
-                const [page, x, y] = dec
+                // a jump carries every position of the source, separated by commas
+                const positions = dec
                     .decode((message[1] as any).buffer)
-                    .split(" ")
-                    .map(Number);
-
-                let pageToJump = page;
+                    .split(",")
+                    .map(position => position.split(" ").map(Number));
 
-                if (previewMode === PreviewMode.Slide) {
-                    const pageSelector = document.getElementById("typst-page-selector") as HTMLSelectElement | undefined;
-                    if (svgDoc.setPartialPageNumber(page)) {
-                        if (pageSelector) {
-                            pageSelector.value = page.toString();
-                        }
-                        // pageToJump = 1;
-                        // todo: hint location
-                        return;
-                    } else {
-                        return;
-                    }
-                }
-
-                const rootElem =
-                    document.getElementById("typst-app")?.firstElementChild;
-                if (rootElem) {
-                    /// Note: when it is really scrolled, it will trigger `svgDoc.addViewportChange`
-                    /// via `window.onscroll` event
-                    window.handleTypstLocation(rootElem, pageToJump, x, y);
+                if (message[0] === "jump") {
+                    occurrences = positions.length > 1 ? { svgDoc, positions, current: 0 } : undefined;
                 }
+                reveal.ignoreScroll();
+                jumpTo(svgDoc, positions[0]);
                 return;
             } else if (message[0] === "cursor") {
                 // todo: aware height padding
@@ -352,6 +481,11 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
                 console.log("cursor-paths", paths);
                 svgDoc.impl.setCursorPaths(paths);
                 return;
+            } else if (message[0] === "selection-paths") {
+                const paths = JSON.parse(dec
+                    .decode((message[1] as any).buffer));
+                svgDoc.impl.setSelectionPaths(paths);
+                return;
             } else if (message[0] === "partial-rendering") {
                 console.log("Experimental feature: partial rendering enabled");
                 svgDoc.setPartialRendering(true);
@@ -364,11 +498,84 @@ export async function wsMain({ url, previewMode, isContentPreview }: WsArgs) {
             } else if (message[0] === "outline") {
                 console.log("Experimental feature: outline rendering");
                 return;
+            } else if (message[0] === "speaker-notes") {
+                const notes = JSON.parse(dec.decode((message[1] as any).buffer));
+                presenter?.setNotes(notes);
+                return;
+            } else if (message[0] === "compile-status") {
+                const status = JSON.parse(dec.decode((message[1] as any).buffer));
+                if (status.kind === "CompileError") {
+                    diagnostics.show(status.data);
+                } else if (status.kind === "CompileSuccess") {
+                    diagnostics.clear();
+                    search.refresh();
+                    follow.refresh();
+                }
+                return;
+            } else if (message[0] === "follow-cursor") {
+                follow.setEnabled(dec.decode((message[1] as any).buffer) === "true");
+                return;
+            } else if (message[0] === "selection") {
+                const selection = JSON.parse(dec.decode((message[1] as any).buffer));
+                selectedText = selection.text;
+                return;
+            } else if (message[0] === "search-result") {
+                const result = JSON.parse(dec.decode((message[1] as any).buffer));
+                search.show(result);
+                return;
+            } else if (message[0] === "session") {
+                const token = dec.decode((message[1] as any).buffer);
+                if (token !== sessionToken) {
+                    sessionToken = token;
+                    docVersion = 0;
+                }
+                return;
+            } else if (message[0] === "delta") {
+                const frame = message[1] as Uint8Array;
+                const versionIdx = frame.indexOf(COMMA[0]);
+                const version = Number.parseInt(dec.decode(frame.slice(0, versionIdx)));
+                const payload = frame.slice(versionIdx + 1);
+                const isSnapshot = dec.decode(payload.slice(0, 4)) === "new,";
+                if (!isSnapshot && version !== docVersion + 1) {
+                    // the frames in between are lost, starts over
+                    console.warn(`render frame ${version} follows ${docVersion}, reconnecting`);
+                    sessionToken = undefined;
+                    window.typstWebsocket.close();
+                    return;
+                }
+                docVersion = version;
+                processMessage(payload.buffer);
+                return;
+            } else if (message[0] === "slide-change") {
+                if (!followingSlides || previewMode !== PreviewMode.Slide) {
+                    return;
+                }
+                pendingLeaderPage = Number.parseInt(dec.decode((message[1] as any).buffer));
+                followLeader();
+                return;
             }
 
             svgDoc.addChangement(message as any);
+            if (pendingLeaderPage !== undefined) {
+                // the page is only available after the document is updated
+                requestAnimationFrame(followLeader);
+            }
         };
 
+        function followLeader() {
+            if (pendingLeaderPage === undefined) {
+                return;
+            }
+            const page = pendingLeaderPage;
+            const pageSelector = document.getElementById("typst-page-selector") as HTMLSelectElement | undefined;
+            if (svgDoc.setPartialPageNumber(page)) {
+                pendingLeaderPage = undefined;
+                if (pageSelector) {
+                    pageSelector.value = page.toString();
+                }
+            }
+        }
+
         return dispose;
     }
 
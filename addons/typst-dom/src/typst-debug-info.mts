@@ -78,6 +78,16 @@ export function removeSourceMappingHandler(docRoot: HTMLElement) {
     delete (docRoot as any).sourceMappingHandler;
     // console.log("remove removeSourceMappingHandler");
   }
+  const prevSelectionStartHandler = (docRoot as any).selectionStartHandler;
+  if (prevSelectionStartHandler) {
+    docRoot.removeEventListener("mousedown", prevSelectionStartHandler);
+    delete (docRoot as any).selectionStartHandler;
+  }
+  const prevSelectionEndHandler = (docRoot as any).selectionEndHandler;
+  if (prevSelectionEndHandler) {
+    docRoot.removeEventListener("mouseup", prevSelectionEndHandler);
+    delete (docRoot as any).selectionEndHandler;
+  }
 }
 
 function findIndexOfChild(elem: Element, child: Element) {
@@ -247,80 +257,88 @@ function getCharIndex(elem: Element, mouseX: number, mouseY: number) {
   return foundIndex;
 }
 
-export function installEditorJumpToHandler(docRoot: HTMLElement) {
-  const collectElementPath = async (event: MouseEvent, elem: Element) => {
-    const visitChain: [SourceMappingType, Element, string][] = [];
-    while (elem) {
-      let srcElem = castToSourceMappingElement(elem);
-      if (srcElem) {
-        if (srcElem[0] === SourceMappingType.CharIndex) {
-          const textElem = elem.parentElement?.parentElement?.parentElement!;
-          let foundIndex = -1;
-          if (textElem) {
-            foundIndex = getCharIndex(textElem, event.clientX, event.clientY);
-          }
-          if (foundIndex !== -1) {
-            (srcElem[1] as any) = foundIndex;
-            visitChain.push(srcElem);
-          }
-        } else {
+/// Collects the path from the page down to a laid-out element, as sent by
+/// `srcpath` messages. The character under the mouse is only looked up if the
+/// event is given.
+export function collectElementPath(docRoot: Element, elem: Element, event?: MouseEvent) {
+  const visitChain: [SourceMappingType, Element, string][] = [];
+  while (elem) {
+    let srcElem = castToSourceMappingElement(elem);
+    if (srcElem) {
+      if (srcElem[0] === SourceMappingType.CharIndex) {
+        const textElem = elem.parentElement?.parentElement?.parentElement!;
+        let foundIndex = -1;
+        if (textElem && event) {
+          foundIndex = getCharIndex(textElem, event.clientX, event.clientY);
+        }
+        if (foundIndex !== -1) {
+          (srcElem[1] as any) = foundIndex;
           visitChain.push(srcElem);
         }
+      } else {
+        visitChain.push(srcElem);
       }
-      if (elem === docRoot) {
-        break;
-      }
-      elem = elem.parentElement!;
     }
-
-    if (visitChain.length === 0) {
-      return undefined;
+    if (elem === docRoot) {
+      break;
     }
+    elem = elem.parentElement!;
+  }
 
-    // console.log('visitChain', visitChain);
+  if (visitChain.length === 0) {
+    return undefined;
+  }
 
-    let startIdx = 1;
-    if (visitChain.length >= 1 && visitChain[0][0] === SourceMappingType.CharIndex) {
-      startIdx = 2;
-    }
-    for (let idx = startIdx; idx < visitChain.length; idx++) {
-      if (visitChain[idx - 1][0] === SourceMappingType.CharIndex) {
-        throw new Error("unexpected");
-      }
+  // console.log('visitChain', visitChain);
 
-      const childIdx = findIndexOfChild(
-        visitChain[idx][1],
-        visitChain[idx - 1][1]
-      );
-      if (childIdx < 0) {
-        return undefined;
-      }
-      (visitChain[idx - 1][1] as any) = childIdx;
+  let startIdx = 1;
+  if (visitChain.length >= 1 && visitChain[0][0] === SourceMappingType.CharIndex) {
+    startIdx = 2;
+  }
+  for (let idx = startIdx; idx < visitChain.length; idx++) {
+    if (visitChain[idx - 1][0] === SourceMappingType.CharIndex) {
+      throw new Error("unexpected");
     }
 
-    visitChain.reverse();
-
-    const pg = visitChain[0];
-    if (pg[0] !== SourceMappingType.Page) {
-      return undefined;
-    }
-    const childIdx = findIndexOfChild(pg[1].parentElement!, visitChain[0][1]);
+    const childIdx = findIndexOfChild(
+      visitChain[idx][1],
+      visitChain[idx - 1][1]
+    );
     if (childIdx < 0) {
       return undefined;
     }
-    (visitChain[0][1] as any) = childIdx;
+    (visitChain[idx - 1][1] as any) = childIdx;
+  }
 
-    const sourceNodePath = visitChain;
-    return sourceNodePath;
-  };
+  visitChain.reverse();
 
+  const pg = visitChain[0];
+  if (pg[0] !== SourceMappingType.Page) {
+    return undefined;
+  }
+  const childIdx = findIndexOfChild(pg[1].parentElement!, visitChain[0][1]);
+  if (childIdx < 0) {
+    return undefined;
+  }
+  (visitChain[0][1] as any) = childIdx;
+
+  const sourceNodePath = visitChain;
+  return sourceNodePath;
+}
+
+export function installEditorJumpToHandler(docRoot: HTMLElement) {
   removeSourceMappingHandler(docRoot);
   const sourceMappingHandler = ((docRoot as any).sourceMappingHandler = async (
     event: MouseEvent
   ) => {
     let elem = event.target! as Element;
 
-    const elementPath = await collectElementPath(event, elem);
+    // a selection is mapped to the source by the selection handlers
+    if (window.getSelection()?.isCollapsed === false) {
+      return;
+    }
+
+    const elementPath = collectElementPath(docRoot, elem, event);
     if (!elementPath) {
       return;
     }
@@ -346,6 +364,39 @@ export function installEditorJumpToHandler(docRoot: HTMLElement) {
   });
 
   docRoot.addEventListener("click", sourceMappingHandler);
+
+  /// The path of the glyph where the selection starts.
+  let selectionStart: any[] | undefined = undefined;
+  /// The index of a character is the one of its end, while a selection
+  /// covers whole glyphs.
+  const toGlyphPath = (path: any[] | undefined) => {
+    const leaf = path?.[path.length - 1];
+    if (leaf && leaf[0] === SourceMappingType.CharIndex) {
+      leaf[1] = Math.max(0, leaf[1] - 1);
+    }
+    return path;
+  };
+  const selectionStartHandler = ((docRoot as any).selectionStartHandler = async (
+    event: MouseEvent
+  ) => {
+    selectionStart = toGlyphPath(collectElementPath(docRoot, event.target as Element, event));
+  });
+  const selectionEndHandler = ((docRoot as any).selectionEndHandler = async (
+    event: MouseEvent
+  ) => {
+    const start = selectionStart;
+    selectionStart = undefined;
+    if (!start || window.getSelection()?.isCollapsed !== false) {
+      return;
+    }
+    const end = toGlyphPath(collectElementPath(docRoot, event.target as Element, event));
+    if (!end) {
+      return;
+    }
+    window.typstWebsocket.send(`selection,${JSON.stringify({ start, end })}`);
+  });
+  docRoot.addEventListener("mousedown", selectionStartHandler);
+  docRoot.addEventListener("mouseup", selectionEndHandler);
 }
 
 export interface TypstDebugJumpDocument {
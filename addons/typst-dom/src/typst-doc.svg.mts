@@ -8,6 +8,7 @@ import { ElementPoint, resolveSourceLeaf } from "./typst-debug-info.mjs";
 
 export interface TypstSvgDocument {
   setCursorPaths(paths: ElementPoint[][]): void;
+  setSelectionPaths(paths: ElementPoint[][]): void;
 }
 
 export function provideSvgDoc<
@@ -35,6 +36,13 @@ export function provideSvgDoc<
       this.addViewportChange();
     }
 
+    /// selection paths are the paths of the characters selected in the editor
+    selectionPaths?: ElementPoint[][] = undefined;
+    setSelectionPaths(paths: ElementPoint[][]) {
+      this.selectionPaths = paths;
+      this.addViewportChange();
+    }
+
     postRender$svg() {
       const docRoot = this.hookedElem.firstElementChild as SVGElement;
       if (docRoot) {
@@ -140,6 +148,41 @@ export function provideSvgDoc<
         }
       }
 
+      if (this.selectionPaths) {
+        for (const c of document.querySelectorAll('.typst-svg-selection')) {
+          c.remove();
+        }
+
+        // Highlights the selected glyphs by element paths
+        for (const p of this.selectionPaths) {
+          const leaf = resolveSourceLeaf(this.hookedElem, p);
+          if (!leaf) {
+            continue;
+          }
+
+          let useIdx = 0;
+          for (const use of leaf[0].children) {
+            // The cursor is after the glyph before the index, while the
+            // selected glyph is the one at the index
+            if (use.tagName !== 'use' || useIdx++ !== leaf[1]) {
+              continue;
+            }
+            const rectBase = (use as SVGUseElement).getBBox();
+            // Spaces have no extent to highlight
+            if (rectBase.width !== 0) {
+              const t = document.createElementNS("http://www.w3.org/2000/svg", "rect");
+              t.classList.add('typst-svg-selection');
+              t.setAttribute('x', `${rectBase.x}`);
+              t.setAttribute('y', `${rectBase.y}`);
+              t.setAttribute('width', `${rectBase.width}`);
+              t.setAttribute('height', `${rectBase.height}`);
+              leaf[0].appendChild(t);
+            }
+            break;
+          }
+        }
+      }
+
       return [t2, t3];
     }
 
@@ -39,9 +39,41 @@ async function loadHTMLFile(context: vscode.ExtensionContext, relativePath: stri
 	return fileContents;
 }
 
-function statusBarItemProcess(event: "Compiling" | "CompileSuccess" | "CompileError") {
+const statusBarTooltip = 'Typst Preview Status: Click to show logs';
+
+interface CompileInfo {
+	compile_ms: number;
+	eval_ms: number | null;
+	layout_iterations: number | null;
+	layout_ms: number | null;
+	page_count: number;
+	first_delta_ms: number | null;
+}
+
+function describeCompileInfo(info: CompileInfo) {
+	const pages = `${info.page_count} page${info.page_count === 1 ? "" : "s"}`;
+	return `compiled in ${Math.round(info.compile_ms)} ms, ${pages}`;
+}
+
+function compileInfoTooltip(info: CompileInfo) {
+	const lines = [`Compiled in ${Math.round(info.compile_ms)} ms`];
+	if (info.eval_ms !== null) {
+		lines.push(`Evaluation: ${Math.round(info.eval_ms)} ms`);
+	}
+	if (info.layout_ms !== null && info.layout_iterations !== null) {
+		lines.push(`Layout: ${Math.round(info.layout_ms)} ms in ${info.layout_iterations} iteration(s)`);
+	}
+	if (info.first_delta_ms !== null) {
+		lines.push(`First update sent after ${Math.round(info.first_delta_ms)} ms`);
+	}
+	lines.push(`Pages: ${info.page_count}`);
+	return lines.join("\n");
+}
+
+function statusBarItemProcess(event: "Compiling" | "CompileSuccess" | "CompileError", info?: CompileInfo) {
 	const style = vscode.workspace.getConfiguration().get<string>('typst-preview.statusBarIndicator') || "compact";
 	if (statusBarItem) {
+		statusBarItem.tooltip = statusBarTooltip;
 		if (event === "Compiling") {
 			if (style === "compact") {
 				statusBarItem.text = "$(sync~spin)";
@@ -54,8 +86,9 @@ function statusBarItemProcess(event: "Compiling" | "CompileSuccess" | "CompileEr
 			if (style === "compact") {
 				statusBarItem.text = "$(typst-guy)";
 			} else if (style === "full") {
-				statusBarItem.text = "$(typst-guy) Compile Success";
+				statusBarItem.text = info ? `$(typst-guy) ${describeCompileInfo(info)}` : "$(typst-guy) Compile Success";
 			}
+			statusBarItem.tooltip = info ? `${compileInfoTooltip(info)}\n\n${statusBarTooltip}` : statusBarTooltip;
 			statusBarItem.backgroundColor = new vscode.ThemeColor("statusBarItem.prominentBackground");
 			statusBarItem.show();
 		} else if (event === "CompileError") {
@@ -376,7 +409,7 @@ const launchPreview = async (task: LaunchInBrowserTask | LaunchInWebViewTask) =>
 			case "editorScrollTo": return await editorScrollTo(activeEditor, data /* JumpInfo */);
 			case "syncEditorChanges": return syncEditorChanges(addonΠserver);
 			case "compileStatus": {
-				statusBarItemProcess(data.kind);
+				statusBarItemProcess(data.kind, data.data /* CompileInfo */);
 				break;
 			}
 			case "outline": {
@@ -813,7 +846,7 @@ export function activate(context: vscode.ExtensionContext) {
 	statusBarItem = vscode.window.createStatusBarItem(vscode.StatusBarAlignment.Left, 0);
 	statusBarItem.name = 'typst-preview';
 	statusBarItem.command = 'typst-preview.showLog';
-	statusBarItem.tooltip = 'Typst Preview Status: Click to show logs';
+	statusBarItem.tooltip = statusBarTooltip;
 
 	// https://github.com/microsoft/vscode-extension-samples/blob/4721ef0c450f36b5bce2ecd5be4f0352ed9e28ab/webview-view-sample/src/extension.ts#L3
 	let contentPreviewHtml = loadHTMLFile(context, "./out/frontend/index.html");
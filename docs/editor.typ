@@ -17,8 +17,16 @@ The editor extension mainly does these things:
 + _In memory editing_: The editor extension should send dirty document to the preview server, without saving it to the disk. This is the most important part of the editor extension. With this, Typst-Preview can get the content of the document and render it every time when user types something.
 + _Source to preview jumping_: This is not necessary, but it's a nice feature to have. With this, the preview panel will be scrolled to the corresponding position when user clicks on the source code.
 + _Preview to source jumping_: This is quite similar to the previous one. With this, the source code will be scrolled to the corresponding position when user clicks on the preview panel.
++ _Cursor highlighting_: With this, the preview panel highlights the text at the cursors and in the selections of the editor.
++ _Follow cursor_: With this, the preview panel keeps the cursor of the editor in view while the user types.
++ _Scroll synchronization_: With this, the source code follows the preview panel as the user scrolls it.
 + _Compile status reporting_: With this, the preview server can report the compile status to the editor extension. The editor extension can show the compile status to the user.
 + _Outline_: The preview server reports current outline to the editor extension. 
++ _Labels_: The preview server reports the labels and references of the document to the editor extension.
++ _Citations_: The preview server reports the citations and bibliography entries of the document to the editor extension.
++ _Document statistics_: The preview server reports the word and character counts of the document to the editor extension.
++ _Query_: The editor extension can run selector queries against the live document.
++ _Search_: The editor extension can search the text of the live document.
 
 == In memory editing
 
@@ -88,6 +96,21 @@ Example:
 }
 ```
 
+The source may be shown more than once in the document, e.g. a heading in the outline and in the running headers. The preview panel jumps to the first place, and the user can step through the rest with `n` and `Shift+N`. The preview server answers every `panelScrollTo` with an `occurrences` message listing all the places in document order, so that the editor can show e.g. "3 occurrences". The list is empty when the source is not shown at all.
+
+```json
+{
+  "event": "occurrences",
+  "filepath": "/home/mgt/proj/typst-preview/docs/dev.typ",
+  "line": 0,
+  "character": 0,
+  "positions": [
+    { "page_no": 1, "x": 72.0, "y": 96.5 },
+    { "page_no": 3, "x": 72.0, "y": 40.2 }
+  ]
+}
+```
+
 == Preview to source jumping
 
 To implement preview to source jumping, the editor extension should listen to the `EditorScrollTo` message from the preview server. The `event` field should be `editorScrollTo`. The `filepath` field is the absolute path of the file. The `start` field is the start position of the selection. The `end` field is the end position of the selection.
@@ -111,6 +134,64 @@ Example:
 }
 ```
 
+When the user selects text in the preview panel, the preview server sends an `editorSelect` message with the source range of the selection, in the same format. The editor extension can select the range to mirror the selection.
+
+```json
+{
+  "event": "editorSelect",
+  "filepath": "/tmp/t/a.typ",
+  "start": [3, 0],
+  "end": [3, 4]
+}
+```
+
+== Cursor highlighting
+
+To highlight the cursor in the preview panel, the editor extension should send a `changeCursorPosition` message whenever the cursor moves. The fields are the same as the ones of `panelScrollTo`.
+
+Editors with several cursors or with a selection can send all of them in the `ranges` field instead. Each range has a `start` and an `end` position as `(row, column)` pairs, both 0-based. An empty range is a cursor, and the preview panel highlights every character of the other ranges. The `line` and `character` fields still give the main cursor.
+
+```json
+{
+  "event": "changeCursorPosition",
+  "filepath": "/home/mgt/proj/typst-preview/docs/dev.typ",
+  "line": 2,
+  "character": 5,
+  "ranges": [
+    { "start": [0, 4], "end": [0, 4] },
+    { "start": [2, 0], "end": [2, 5] }
+  ]
+}
+```
+
+== Follow cursor
+
+In the follow-cursor mode, the preview server scrolls each preview panel to the cursor received by `changeCursorPosition`, once the cursor leaves the region the panel shows. The cursor is followed once it rests for 300ms, so that the panels do not jitter while the user types.
+
+The mode is shared by the editor and all the preview panels, and it is off at first. The editor extension turns it on or off with a `followCursor` message, and the user can toggle it in the preview panel too. The preview server sends a `followCursor` message to the editor extension whenever the mode changes, so that it can show the state.
+
+```json
+{
+  "event": "followCursor",
+  "enabled": true
+}
+```
+
+== Scroll synchronization
+
+When the user scrolls a preview panel, the panel reports the text shown at its top, and the preview server sends an `editorRevealRange` message with the source range of that text, in the same format as `editorSelect`. The range is sent at most every 300ms while the user scrolls, and not at all for the scrolls the preview panel makes on behalf of the editor.
+
+The editor extension should only reveal the range, without moving the cursor, and only if the user turned on scroll lock, as it does not know whether the user is reading the preview or writing.
+
+```json
+{
+  "event": "editorRevealRange",
+  "filepath": "/tmp/t/a.typ",
+  "start": [12, 0],
+  "end": [12, 38]
+}
+```
+
 == Compile Status Reporting
 
 To implement compile status reporting, the editor extension act on `compileStatus` event. The `event` field should be `compileStatus`. The `kind` field is the compile status. The `kind` field can be one of the following values:
@@ -171,6 +252,181 @@ To implement outline reporting, the editor extension should listen to the `outli
 }
 ```
 
+== Labels
+
+The preview server sends a `labels` event after each successful compilation, which the editor extension can use for `@` completion and "go to label". Each label carries the `kind` of the labelled element, the `figure_kind` of figures, the displayed `numbering` of numbered elements, and its `span` and `position` like the outline. The numbers are displayed again by the preview server with the standard library only, so a numbering reading files, fonts, the date or `sys.inputs` is left out or may differ from the document, and contextual numberings see the default styles. Each reference carries its `target` label or bibliography key and whether it is `resolved`. Note that Typst fails to compile a document referencing a missing label, in which case the error is reported by `compileStatus`, and the last `labels` event describes the last compiled document.
+
+```json
+{
+  "event": "labels",
+  "labels": [
+    {
+      "label": "tab",
+      "kind": "figure",
+      "figure_kind": "table",
+      "numbering": "1",
+      "span": "300000007",
+      "position": {
+        "page_no": 1,
+        "x": 275.6114,
+        "y": 138.27025
+      }
+    }
+  ],
+  "references": [
+    {
+      "target": "tab",
+      "resolved": true,
+      "span": "30000000a",
+      "position": {
+        "page_no": 1,
+        "x": 216.91167,
+        "y": 117.83002
+      }
+    }
+  ]
+}
+```
+
+== Citations
+
+The preview server sends a `citations` event along with the outline. It lists every citation of the document with its bibliography `key`, `span` and `position`, and every entry of the bibliographies with its number of `citations` and whether it is `rendered`. Entries with no citations are unused, unless the bibliography is `full`.
+
+```json
+{
+  "event": "citations",
+  "citations": [
+    {
+      "key": "knuth",
+      "span": "200000003",
+      "position": {
+        "page_no": 1,
+        "x": 271.106,
+        "y": 117.83002
+      }
+    }
+  ],
+  "entries": [
+    {
+      "key": "knuth",
+      "title": "The Art of Computer Programming",
+      "citations": 1,
+      "rendered": true
+    },
+    {
+      "key": "unused",
+      "title": "Other",
+      "citations": 0,
+      "rendered": false
+    }
+  ]
+}
+```
+
+== Document Statistics
+
+The preview server sends a `documentStats` event along with the outline. It reports the number of `pages`, and the number of `words` and `characters` (not counting whitespace) of the laid-out text, in total and for each section of the outline. The counts of a section include its subsections, and the text before the first heading only counts in the total. The text is read in the order of layout, column after column, and the running headers, footers and page numbers repeated on the pages are not counted. Note that the text the body repeats at the very top or bottom of several pages, e.g. in a loop, is taken for a header or footer too. Figures, footnotes and the bibliography can be left out of the counts with `--stats-exclude figures,footnotes,bibliography`.
+
+```json
+{
+  "event": "documentStats",
+  "pages": 12,
+  "words": 3120,
+  "characters": 16804,
+  "sections": [
+    {
+      "title": "Introduction",
+      "span": "200000001",
+      "position": {
+        "page_no": 1,
+        "x": 70.86625,
+        "y": 97.90649
+      },
+      "words": 812,
+      "characters": 4410,
+      "children": []
+    }
+  ]
+}
+```
+
+== Query
+
+To query the document, the editor extension should send the `query` message to the preview server. The `selector` field is a selector in Typst code, like the one of `typst query`, e.g. `<label>`, `metadata` or `figure.where(kind: table)`. The optional `id` field is echoed in the response.
+
+```json
+{
+  "event": "query",
+  "id": 1,
+  "selector": "figure.where(kind: table)"
+}
+```
+
+The preview server answers with a `queryResult` event. Each matched element carries its serialized `fields`, its `position` in the document and a `span` id, which can be sent back in a `sourceScrollBySpan` message to jump to its source. Like the spans of the outline, it stays valid until 30 more documents are compiled. If the selector is invalid, `elements` is empty and `diagnostics` holds the errors, in the same format as the ones of `CompileError`.
+
+```json
+{
+  "event": "queryResult",
+  "id": 1,
+  "elements": [
+    {
+      "fields": {
+        "func": "metadata",
+        "value": "note",
+        "label": "<m>"
+      },
+      "span": "300000005",
+      "position": {
+        "page_no": 1,
+        "x": 134.32036,
+        "y": 125.07025
+      }
+    }
+  ],
+  "diagnostics": []
+}
+```
+
+== Search
+
+To search the laid-out text of the document, the editor extension should send the `search` message to the preview server. The search ignores case unless `case_sensitive` is true, and a whitespace in the `query` matches any whitespace, including line breaks. The optional `id` field is echoed in the result.
+
+```json
+{
+  "event": "search",
+  "id": 1,
+  "query": "hello world",
+  "case_sensitive": false
+}
+```
+
+The preview server answers with a `searchResult` event. Each hit carries the `ranges` it covers in the document, one for each run of text, from the top left `start` to the bottom right `end`, and the `span` id of its first character, which can be sent back in a `sourceScrollBySpan` message to jump to its source. At most 1000 hits are reported, and `truncated` tells whether there are more.
+
+```json
+{
+  "event": "searchResult",
+  "id": 1,
+  "query": "hello world",
+  "hits": [
+    {
+      "span": "30000000f",
+      "ranges": [
+        {
+          "start": { "page_no": 1, "x": 83.3968, "y": 67.03129 },
+          "end": { "page_no": 1, "x": 121.98703, "y": 84.58939 }
+        },
+        {
+          "start": { "page_no": 1, "x": 70.86625, "y": 86.455315 },
+          "end": { "page_no": 1, "x": 98.538124, "y": 98.99682 }
+        }
+      ]
+    }
+  ],
+  "truncated": false
+}
+```
+
+The webview searches the same way, by sending `search,<json>` with the fields of the `search` message, and it is answered with `search-result,<json>`.
 
 == References
 
@@ -194,6 +450,12 @@ enum ControlPlaneMessage {
     UpdateMemoryFiles(MemoryFiles),
     #[serde(rename = "removeMemoryFiles")]
     RemoveMemoryFiles(MemoryFilesShort),
+    #[serde(rename = "query")]
+    Query(QueryRequest),
+    #[serde(rename = "search")]
+    Search(SearchRequest),
+    #[serde(rename = "followCursor")]
+    FollowCursor(FollowCursorState),
 }
 
 ```
@@ -206,11 +468,29 @@ Messages sent from the preview server to the editor extension, defined in `src/a
 enum ControlPlaneResponse {
     #[serde(rename = "editorScrollTo")]
     EditorScrollTo(DocToSrcJumpInfo),
+    #[serde(rename = "editorSelect")]
+    EditorSelect(SourceRange),
+    #[serde(rename = "editorRevealRange")]
+    EditorRevealRange(SourceRange),
+    #[serde(rename = "occurrences")]
+    Occurrences(Occurrences),
     #[serde(rename = "syncEditorChanges")]
     SyncEditorChanges(()),
     #[serde(rename = "compileStatus")]
     CompileStatus(CompileStatus),
     #[serde(rename = "outline")]
     Outline(Outline),
+    #[serde(rename = "labels")]
+    Labels(Labels),
+    #[serde(rename = "citations")]
+    Citations(Citations),
+    #[serde(rename = "documentStats")]
+    DocumentStats(DocumentStats),
+    #[serde(rename = "queryResult")]
+    QueryResult(QueryResponse),
+    #[serde(rename = "searchResult")]
+    SearchResult(SearchResult),
+    #[serde(rename = "followCursor")]
+    FollowCursor(FollowCursorState),
 }
 ```
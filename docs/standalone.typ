@@ -72,6 +72,28 @@ typst-preview --no-open \
   /path/to/my-super-cool-doc.typ
 ```
 
+7. Use `typst-preview` to present your slides with speaker notes. Open `http://127.0.0.1:23627/presenter` in another window to get the presenter view, which shows a timer, the notes of the current slide and the next slide. The audience views follow the slide changes made in the presenter view. Speaker notes are collected from the `pdfpc` metadata emitted by polylux and touying.
+
+Any other slide preview can also lead the slides of all connected viewers by pressing `l`, and a viewer can stop following the leader by pressing `f`. An editor can drive the slides by sending a `panelSlideTo` event with a `page_no` to the control plane.
+
+```bash
+typst-preview --preview-mode slide \
+  /path/to/my-slides.typ
+```
+
+== HTTP Endpoints
+
+Besides the preview itself, the preview server serves the following endpoints:
+
+- `/page/<n>.png?ppi=<ppi>`: renders the page `n` (starting at 1) of the latest compiled document to PNG. The resolution defaults to 144 pixels per inch, and is lowered for large pages to render at most 50 million pixels.
+- `/page/<n>.svg`: renders the page `n` of the latest compiled document to SVG.
+- `/status`: reports the status of the preview as JSON, including the status and duration of the latest compilation, the page count of the document, the number of connected webviews and whether an editor is attached.
+- `/healthz`: responds `ok` as long as the preview server is alive.
+- `/readyz`: responds `ok` once a document is compiled, or `503 Service Unavailable` before that.
+- `/metrics`: exposes metrics in the Prometheus text format, including compile durations, render durations and the size of rendered payloads per connection, events dropped by lagging actors and messages received from the editor per event type.
+- `/await_tree` and `/await_tree.json`: dump the await-trees of the actors as text or JSON, including the current await point of each actor and how long it has been waiting there. Actors stuck in one await point for longer than `--stall-threshold` seconds (10 by default) are logged and listed under `stalls` in `/status`.
+- Any other path is looked up under the root directory of your project, so that relative links and attachments in the preview work in the browser. Files outside the root directory and hidden files are never served.
+
 == CLI Options
 
 
@@ -85,7 +107,11 @@ Options:
       --font-path <DIR>    Add additional directories to search for fonts
       --root <DIR>         Root directory for your project
       --host <HOST>        Host for the preview server [default: 127.0.0.1:23627]
+      --phase-timing       Time the evaluation and the layout iterations of each compilation, and report them along with the compile time. This records every scope the compiler enters, which slows compilations down a bit
       --no-open            Don't open the preview in the browser after compilation
       --partial-rendering  Only render visible part of the document. This can improve performance but still being experimental
+      --compile-debounce <MS>  Wait at least this many milliseconds after an edit from the editor before compiling, so that the edits made in the meantime are compiled together [default: 0]
+      --stall-threshold <SECS>  Report an actor as stalled if it is stuck in one await point for longer than this many seconds. Set to 0 to disable the watchdog [default: 10]
+      --stats-exclude <KINDS>  Leave these out of the word and character counts of the document statistics, separated by commas [possible values: figures, footnotes, bibliography]
   -h, --help               Print help
 ```
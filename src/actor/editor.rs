@@ -1,21 +1,32 @@
+use std::time::Duration;
+
 use await_tree::InstrumentAwait;
 use futures::{SinkExt, StreamExt};
 use log::{debug, info, trace, warn};
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{self, Instant};
 use tokio::{net::TcpStream, sync::broadcast};
 use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
 use typst_ts_core::debug_loc::DocumentPosition;
 
 use crate::await_tree::REGISTRY;
+use crate::citations::Citations;
 use crate::debug_loc::{InternQuery, SpanInterner};
+use crate::diagnostics::CompileDiagnostic;
+use crate::labels::Labels;
+use crate::metrics::CONTROL_PLANE_MESSAGES;
 use crate::outline::Outline;
+use crate::query::{query_response, QueryResponse};
+use crate::search::{DocumentSearch, SearchResult};
+use crate::selection::SourceRange;
+use crate::stats::DocumentStats;
 use crate::{
     actor::typst::TypstActorRequest, ChangeCursorPositionRequest, DocToSrcJumpInfo, MemoryFiles,
-    MemoryFilesShort, SrcToDocJumpRequest,
+    MemoryFilesShort, QueryRequest, QueryResult, SearchRequest, SrcToDocJumpRequest,
 };
 
-use super::webview::WebviewActorRequest;
+use super::webview::{FollowCursor, SlideLeader, SlideSync, WebviewActorRequest};
 #[derive(Debug, Deserialize)]
 pub struct DocToSrcJumpResolveRequest {
     /// Span id in hex-format.
@@ -27,20 +38,77 @@ pub struct PanelScrollByPositionRequest {
     position: DocumentPosition,
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
+pub struct PanelSlideToRequest {
+    /// The page to show on all following webviews, starting at 1.
+    page_no: usize,
+}
+
+/// The follow-cursor mode, as set by the editor and reported to it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FollowCursorState {
+    enabled: bool,
+}
+
+/// How long the cursor rests before the webviews following it scroll to it.
+const FOLLOW_CURSOR_DELAY: Duration = Duration::from_millis(300);
+
+/// The least time between two reveals of the source at the top of a webview.
+const REVEAL_RANGE_INTERVAL: Duration = Duration::from_millis(300);
+
+/// The positions where a source location is shown in the document.
+#[derive(Debug, Serialize)]
+pub struct Occurrences {
+    pub filepath: String,
+    pub line: usize,
+    pub character: usize,
+    /// The positions in document order, the webviews jump to the first one.
+    pub positions: Vec<DocumentPosition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind", content = "data")]
 pub enum CompileStatus {
     Compiling,
-    CompileSuccess,
-    CompileError,
+    CompileSuccess(CompileInfo),
+    CompileError(Vec<CompileDiagnostic>),
+}
+
+/// Statistics of a successful compilation, in milliseconds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompileInfo {
+    /// Total compile time.
+    pub compile_ms: f64,
+    /// Time spent on evaluating the sources, absent if they are cached or
+    /// the phases are not timed.
+    pub eval_ms: Option<f64>,
+    /// Number of layout iterations until the introspection converges, absent
+    /// if the phases are not timed.
+    pub layout_iterations: Option<usize>,
+    /// Time spent on the layout iterations, absent if the phases are not
+    /// timed.
+    pub layout_ms: Option<f64>,
+    pub page_count: usize,
+    /// Time from the start of the compilation until the first delta is sent
+    /// to a webview. It is only known in a second `CompileSuccess` status
+    /// sent after the delta.
+    pub first_delta_ms: Option<f64>,
 }
 
 #[derive(Debug)]
 pub enum EditorActorRequest {
     DocToSrcJumpResolve(DocToSrcJumpResolveRequest),
     DocToSrcJump(DocToSrcJumpInfo),
+    Select(SourceRange),
+    /// The source of the element at the top of a scrolled webview.
+    RevealRange(SourceRange),
+    Occurrences(Occurrences),
     Outline(Outline),
+    Labels(Labels),
+    Citations(Citations),
+    DocumentStats(DocumentStats),
     CompileStatus(CompileStatus),
+    QueryResult(Option<serde_json::Value>, QueryResult),
 }
 
 pub struct EditorActor {
@@ -49,8 +117,16 @@ pub struct EditorActor {
 
     world_sender: mpsc::UnboundedSender<TypstActorRequest>,
     webview_sender: broadcast::Sender<WebviewActorRequest>,
+    slide_sync: SlideSync,
 
     span_interner: SpanInterner,
+    search: DocumentSearch,
+    follow_cursor: FollowCursor,
+    follow_receiver: watch::Receiver<bool>,
+    /// The latest cursor to follow, and when to follow it.
+    pending_follow: Option<(Instant, ChangeCursorPositionRequest)>,
+    /// The latest range to reveal, and when to reveal it.
+    pending_reveal: Option<(Instant, SourceRange)>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -62,6 +138,8 @@ enum ControlPlaneMessage {
     SrcToDocJump(SrcToDocJumpRequest),
     #[serde(rename = "panelScrollByPosition")]
     PanelScrollByPosition(PanelScrollByPositionRequest),
+    #[serde(rename = "panelSlideTo")]
+    PanelSlideTo(PanelSlideToRequest),
     #[serde(rename = "sourceScrollBySpan")]
     DocToSrcJumpResolve(DocToSrcJumpResolveRequest),
     #[serde(rename = "syncMemoryFiles")]
@@ -70,6 +148,31 @@ enum ControlPlaneMessage {
     UpdateMemoryFiles(MemoryFiles),
     #[serde(rename = "removeMemoryFiles")]
     RemoveMemoryFiles(MemoryFilesShort),
+    #[serde(rename = "query")]
+    Query(QueryRequest),
+    #[serde(rename = "search")]
+    Search(SearchRequest),
+    #[serde(rename = "followCursor")]
+    FollowCursor(FollowCursorState),
+}
+
+impl ControlPlaneMessage {
+    /// The event type of the message, as named on the wire.
+    fn event(&self) -> &'static str {
+        match self {
+            Self::ChangeCursorPosition(_) => "changeCursorPosition",
+            Self::SrcToDocJump(_) => "panelScrollTo",
+            Self::PanelScrollByPosition(_) => "panelScrollByPosition",
+            Self::PanelSlideTo(_) => "panelSlideTo",
+            Self::DocToSrcJumpResolve(_) => "sourceScrollBySpan",
+            Self::SyncMemoryFiles(_) => "syncMemoryFiles",
+            Self::UpdateMemoryFiles(_) => "updateMemoryFiles",
+            Self::RemoveMemoryFiles(_) => "removeMemoryFiles",
+            Self::Query(_) => "query",
+            Self::Search(_) => "search",
+            Self::FollowCursor(_) => "followCursor",
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -77,29 +180,58 @@ enum ControlPlaneMessage {
 enum ControlPlaneResponse {
     #[serde(rename = "editorScrollTo")]
     EditorScrollTo(DocToSrcJumpInfo),
+    #[serde(rename = "editorSelect")]
+    EditorSelect(SourceRange),
+    #[serde(rename = "editorRevealRange")]
+    EditorRevealRange(SourceRange),
+    #[serde(rename = "occurrences")]
+    Occurrences(Occurrences),
     #[serde(rename = "syncEditorChanges")]
     SyncEditorChanges(()),
     #[serde(rename = "compileStatus")]
     CompileStatus(CompileStatus),
     #[serde(rename = "outline")]
     Outline(Outline),
+    #[serde(rename = "labels")]
+    Labels(Labels),
+    #[serde(rename = "citations")]
+    Citations(Citations),
+    #[serde(rename = "documentStats")]
+    DocumentStats(DocumentStats),
+    #[serde(rename = "queryResult")]
+    QueryResult(QueryResponse),
+    #[serde(rename = "searchResult")]
+    SearchResult(SearchResult),
+    #[serde(rename = "followCursor")]
+    FollowCursor(FollowCursorState),
 }
 
 impl EditorActor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         mailbox: mpsc::UnboundedReceiver<EditorActorRequest>,
         editor_websocket_conn: WebSocketStream<TcpStream>,
         world_sender: mpsc::UnboundedSender<TypstActorRequest>,
         webview_sender: broadcast::Sender<WebviewActorRequest>,
+        slide_sync: SlideSync,
         span_interner: SpanInterner,
+        search: DocumentSearch,
+        follow_cursor: FollowCursor,
     ) -> Self {
+        let follow_receiver = follow_cursor.subscribe();
         Self {
             mailbox,
             editor_websocket_conn,
             world_sender,
             webview_sender,
+            slide_sync,
 
             span_interner,
+            search,
+            follow_cursor,
+            follow_receiver,
+            pending_follow: None,
+            pending_reveal: None,
         }
     }
 
@@ -134,6 +266,32 @@ impl EditorActor {
                                 break;
                             };
                         },
+                        EditorActorRequest::Select(range) => {
+                            let Ok(_) = self.editor_websocket_conn.send(Message::Text(
+                                serde_json::to_string(&ControlPlaneResponse::EditorSelect(range)).unwrap(),
+                            ))
+                            .instrument_await("send Select message to editor")
+                            .await else {
+                                warn!("EditorActor: failed to send Select message to editor");
+                                break;
+                            };
+                        },
+                        EditorActorRequest::RevealRange(range) => {
+                            // reveals the latest range at most once in an interval
+                            let deadline = self.pending_reveal.take()
+                                .map_or_else(|| Instant::now() + REVEAL_RANGE_INTERVAL, |(deadline, _)| deadline);
+                            self.pending_reveal = Some((deadline, range));
+                        },
+                        EditorActorRequest::Occurrences(occurrences) => {
+                            let Ok(_) = self.editor_websocket_conn.send(Message::Text(
+                                serde_json::to_string(&ControlPlaneResponse::Occurrences(occurrences)).unwrap(),
+                            ))
+                            .instrument_await("send Occurrences message to editor")
+                            .await else {
+                                warn!("EditorActor: failed to send Occurrences message to editor");
+                                break;
+                            };
+                        },
                         EditorActorRequest::DocToSrcJumpResolve(req) => {
                             self.source_scroll_by_span(req.span)
                                 .instrument_await("source scroll by span")
@@ -159,18 +317,102 @@ impl EditorActor {
                                 break;
                             };
                         }
+                        EditorActorRequest::Citations(citations) => {
+                            let Ok(_) = self.editor_websocket_conn.send(Message::Text(
+                                serde_json::to_string(&ControlPlaneResponse::Citations(citations)).unwrap(),
+                            ))
+                                .instrument_await("send Citations message to editor")
+                                .await else {
+                                warn!("EditorActor: failed to send Citations message to editor");
+                                break;
+                            };
+                        }
+                        EditorActorRequest::DocumentStats(stats) => {
+                            let Ok(_) = self.editor_websocket_conn.send(Message::Text(
+                                serde_json::to_string(&ControlPlaneResponse::DocumentStats(stats)).unwrap(),
+                            ))
+                                .instrument_await("send DocumentStats message to editor")
+                                .await else {
+                                warn!("EditorActor: failed to send DocumentStats message to editor");
+                                break;
+                            };
+                        }
+                        EditorActorRequest::Labels(labels) => {
+                            let Ok(_) = self.editor_websocket_conn.send(Message::Text(
+                                serde_json::to_string(&ControlPlaneResponse::Labels(labels)).unwrap(),
+                            ))
+                                .instrument_await("send Labels message to editor")
+                                .await else {
+                                warn!("EditorActor: failed to send Labels message to editor");
+                                break;
+                            };
+                        }
+                        EditorActorRequest::QueryResult(id, result) => {
+                            let response = self.span_interner
+                                .with_writer(|interner| query_response(interner, id, result))
+                                .instrument_await("interning spans of query result")
+                                .await;
+                            let Ok(_) = self.editor_websocket_conn.send(Message::Text(
+                                serde_json::to_string(&ControlPlaneResponse::QueryResult(response)).unwrap(),
+                            ))
+                                .instrument_await("send QueryResult message to editor")
+                                .await else {
+                                warn!("EditorActor: failed to send QueryResult message to editor");
+                                break;
+                            };
+                        }
                     }
                 }
+                _ = time::sleep_until(self.pending_follow.as_ref().map_or_else(Instant::now, |(deadline, _)| *deadline)), if self.pending_follow.is_some() => {
+                    let (_, cursor_info) = self.pending_follow.take().unwrap();
+                    self.world_sender.send(TypstActorRequest::FollowCursor(cursor_info)).unwrap();
+                }
+                _ = time::sleep_until(self.pending_reveal.as_ref().map_or_else(Instant::now, |(deadline, _)| *deadline)), if self.pending_reveal.is_some() => {
+                    let (_, range) = self.pending_reveal.take().unwrap();
+                    let Ok(_) = self.editor_websocket_conn.send(Message::Text(
+                        serde_json::to_string(&ControlPlaneResponse::EditorRevealRange(range)).unwrap(),
+                    ))
+                    .instrument_await("send RevealRange message to editor")
+                    .await else {
+                        warn!("EditorActor: failed to send RevealRange message to editor");
+                        break;
+                    };
+                }
+                Ok(()) = self.follow_receiver.changed().instrument_await("waiting for follow cursor change") => {
+                    let enabled = *self.follow_receiver.borrow_and_update();
+                    if !enabled {
+                        self.pending_follow = None;
+                    }
+                    let Ok(_) = self.editor_websocket_conn.send(Message::Text(
+                        serde_json::to_string(&ControlPlaneResponse::FollowCursor(FollowCursorState { enabled })).unwrap(),
+                    ))
+                        .instrument_await("send FollowCursor message to editor")
+                        .await else {
+                        warn!("EditorActor: failed to send FollowCursor message to editor");
+                        break;
+                    };
+                }
                 Some(Ok(Message::Text(msg))) = self.editor_websocket_conn.next().instrument_await("waiting for websocket") => {
                     let Ok(msg) = serde_json::from_str::<ControlPlaneMessage>(&msg) else {
                         warn!("failed to parse jump request: {:?}", msg);
+                        CONTROL_PLANE_MESSAGES.with_label_values(&["invalid"]).inc();
                         continue;
                     };
+                    CONTROL_PLANE_MESSAGES.with_label_values(&[msg.event()]).inc();
                     match msg {
                         ControlPlaneMessage::ChangeCursorPosition(cursor_info) => {
                             debug!("EditorActor: received message from editor: {:?}", cursor_info);
+                            if *self.follow_cursor.borrow() {
+                                // follows the cursor once it rests
+                                let deadline = Instant::now() + FOLLOW_CURSOR_DELAY;
+                                self.pending_follow = Some((deadline, cursor_info.clone()));
+                            }
                             self.world_sender.send(TypstActorRequest::ChangeCursorPosition(cursor_info)).unwrap();
                         }
+                        ControlPlaneMessage::FollowCursor(FollowCursorState { enabled }) => {
+                            debug!("EditorActor: received message from editor: FollowCursor {}", enabled);
+                            self.follow_cursor.send_if_modified(|state| std::mem::replace(state, enabled) != enabled);
+                        }
                         ControlPlaneMessage::SrcToDocJump(jump_info) => {
                             debug!("EditorActor: received message from editor: {:?}", jump_info);
                             self.world_sender.send(TypstActorRequest::SrcToDocJumpResolve(jump_info)).unwrap();
@@ -179,6 +421,14 @@ impl EditorActor {
                             debug!("EditorActor: received message from editor: {:?}", jump_info);
                             self.webview_sender.send(WebviewActorRequest::ViewportPosition(jump_info.position)).unwrap();
                         }
+                        ControlPlaneMessage::PanelSlideTo(req) => {
+                            debug!("EditorActor: received message from editor: {:?}", req);
+                            // the editor takes over the slides from any leading webview
+                            self.slide_sync.send_modify(|state| {
+                                state.leader = Some(SlideLeader::Editor);
+                                state.page_no = req.page_no;
+                            });
+                        }
                         ControlPlaneMessage::DocToSrcJumpResolve(jump_info) => {
                             debug!("EditorActor: received message from editor: {:?}", jump_info);
 
@@ -198,6 +448,24 @@ impl EditorActor {
                             debug!("EditorActor: received message from editor: RemoveMemoryFiles {:?}", &memory_files.files);
                             self.world_sender.send(TypstActorRequest::RemoveMemoryFiles(memory_files)).unwrap();
                         }
+                        ControlPlaneMessage::Query(req) => {
+                            debug!("EditorActor: received message from editor: {:?}", req);
+                            self.world_sender.send(TypstActorRequest::Query(req)).unwrap();
+                        }
+                        ControlPlaneMessage::Search(req) => {
+                            debug!("EditorActor: received message from editor: {:?}", req);
+                            let result = self.search.search(&req)
+                                .instrument_await("search document")
+                                .await;
+                            let Ok(_) = self.editor_websocket_conn.send(Message::Text(
+                                serde_json::to_string(&ControlPlaneResponse::SearchResult(result)).unwrap(),
+                            ))
+                                .instrument_await("send SearchResult message to editor")
+                                .await else {
+                                warn!("EditorActor: failed to send SearchResult message to editor");
+                                break;
+                            };
+                        }
                     };
                 }
             }
@@ -1,27 +1,57 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use await_tree::InstrumentAwait;
-use log::{debug, info, trace};
-use tokio::sync::{broadcast, mpsc, watch};
+use log::{debug, error, info, trace};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use typst::model::Document;
 use typst_ts_core::debug_loc::{ElementPoint, SourceSpanOffset};
 use typst_ts_core::TypstDocument;
 use typst_ts_svg_exporter::IncrSvgDocServer;
 
 use crate::await_tree::REGISTRY;
-use crate::{debug_loc::SpanInterner, outline::Outline};
+use crate::metrics::{
+    ConnectionLabel, DROPPED_DELTAS, LAGGED_EVENTS, RENDER_BYTES, RENDER_DURATION,
+};
+use crate::{
+    citations::Citations, debug_loc::SpanInterner, labels::Labels, outline::Outline,
+    selection::SelectionSpans, stats::DocumentStats, CompileStatus, CursorSpans, SelectedText,
+    StatsExclusion, StatusTracker,
+};
 
+use super::session::{RenderSession, RenderSessions, SessionRequest};
 use super::{editor::EditorActorRequest, typst::TypstActorRequest, webview::WebviewActorRequest};
 
 #[derive(Debug, Clone)]
 pub struct ResolveSpanRequest(pub Vec<ElementPoint>);
 
+/// A selection made in a webview, from the element path of its first glyph to
+/// the one of its last glyph.
+#[derive(Debug, Clone)]
+pub struct ResolveSelectionRequest {
+    /// The id of the webview, whose render actor resolves the paths.
+    pub webview: usize,
+    pub start: Vec<ElementPoint>,
+    pub end: Vec<ElementPoint>,
+}
+
+/// The element shown at the top of a webview, whose source is revealed in the
+/// editor.
+#[derive(Debug, Clone)]
+pub struct ResolveRevealRequest {
+    /// The id of the webview, whose render actor resolves the path.
+    pub webview: usize,
+    pub path: Vec<ElementPoint>,
+}
+
 #[derive(Debug, Clone)]
 pub enum RenderActorRequest {
     RenderFullLatest,
     RenderIncremental,
     ResolveSpan(ResolveSpanRequest),
-    ChangeCursorPosition(SourceSpanOffset),
+    ResolveSelection(ResolveSelectionRequest),
+    ResolveReveal(ResolveRevealRequest),
+    ChangeCursorPosition(CursorSpans),
 }
 
 impl RenderActorRequest {
@@ -30,6 +60,8 @@ impl RenderActorRequest {
             Self::RenderFullLatest => true,
             Self::RenderIncremental => false,
             Self::ResolveSpan(_) => false,
+            Self::ResolveSelection(_) => false,
+            Self::ResolveReveal(_) => false,
             Self::ChangeCursorPosition(_) => false,
         }
     }
@@ -38,10 +70,30 @@ impl RenderActorRequest {
 pub struct RenderActor {
     mailbox: broadcast::Receiver<RenderActorRequest>,
     document: watch::Receiver<Option<Arc<Document>>>,
-    renderer: IncrSvgDocServer,
+    session: RenderSession,
     resolve_sender: mpsc::UnboundedSender<TypstActorRequest>,
-    svg_sender: mpsc::UnboundedSender<Vec<u8>>,
+    svg_sender: mpsc::Sender<Vec<u8>>,
     webview_sender: broadcast::Sender<WebviewActorRequest>,
+    editor_sender: mpsc::UnboundedSender<EditorActorRequest>,
+    status: StatusTracker,
+    /// Whether the webview fell behind and missed some deltas, so that it has
+    /// to be sent a full snapshot once it catches up.
+    needs_snapshot: bool,
+    /// The sessions to resume and detach, with the request of the webview
+    /// that is waited for before rendering.
+    sessions: Option<RenderSessions>,
+    session_request: Option<oneshot::Receiver<SessionRequest>>,
+    /// The id of the webview served by the actor.
+    webview: Option<usize>,
+    /// The label of the render metrics of the actor.
+    metrics_label: ConnectionLabel,
+}
+
+/// Wakes up a render actor.
+enum Wakeup {
+    Message(Result<RenderActorRequest, broadcast::error::RecvError>),
+    /// The webview has caught up, see [`RenderActor::needs_snapshot`].
+    CaughtUp,
 }
 
 impl RenderActor {
@@ -49,19 +101,45 @@ impl RenderActor {
         mailbox: broadcast::Receiver<RenderActorRequest>,
         document: watch::Receiver<Option<Arc<Document>>>,
         resolve_sender: mpsc::UnboundedSender<TypstActorRequest>,
-        svg_sender: mpsc::UnboundedSender<Vec<u8>>,
+        svg_sender: mpsc::Sender<Vec<u8>>,
         webview_sender: broadcast::Sender<WebviewActorRequest>,
+        editor_sender: mpsc::UnboundedSender<EditorActorRequest>,
+        status: StatusTracker,
     ) -> Self {
-        let mut res = Self {
+        Self {
             mailbox,
             document,
-            renderer: IncrSvgDocServer::default(),
+            session: RenderSession::default(),
             resolve_sender,
             svg_sender,
             webview_sender,
-        };
-        res.renderer.set_should_attach_debug_info(true);
-        res
+            editor_sender,
+            status,
+            needs_snapshot: false,
+            sessions: None,
+            session_request: None,
+            webview: None,
+            metrics_label: ConnectionLabel::acquire(),
+        }
+    }
+
+    /// Serves the requests of the webview, which only its own render actor
+    /// answers.
+    pub fn with_webview(mut self, webview: usize) -> Self {
+        self.webview = Some(webview);
+        self
+    }
+
+    /// Waits for the webview to start or resume a session before rendering,
+    /// and keeps the session for resuming after the webview disconnects.
+    pub fn with_sessions(
+        mut self,
+        sessions: RenderSessions,
+        session_request: oneshot::Receiver<SessionRequest>,
+    ) -> Self {
+        self.sessions = Some(sessions);
+        self.session_request = Some(session_request);
+        self
     }
 
     pub fn spawn(self, peer_addr: String) {
@@ -79,7 +157,11 @@ impl RenderActor {
         match msg {
             RenderActorRequest::ResolveSpan(ResolveSpanRequest(element_path)) => {
                 info!("RenderActor: resolving span: {:?}", element_path);
-                let spans = match self.renderer.resolve_span_by_element_path(&element_path) {
+                let spans = match self
+                    .session
+                    .renderer
+                    .resolve_span_by_element_path(&element_path)
+                {
                     Ok(spans) => spans,
                     Err(e) => {
                         info!("RenderActor: failed to resolve span: {}", e);
@@ -99,18 +181,85 @@ impl RenderActor {
                     };
                 }
             }
-            RenderActorRequest::ChangeCursorPosition(span_offset) => {
-                info!("RenderActor: changing cursor position: {:?}", span_offset);
-
-                let res = self.renderer.resolve_element_paths_by_span(span_offset);
+            RenderActorRequest::ResolveSelection(req) => {
+                if self.webview != Some(req.webview) {
+                    return false;
+                }
+                info!("RenderActor: resolving selection: {:?}", req);
+                let renderer = &mut self.session.renderer;
+                let spans = renderer
+                    .resolve_span_by_element_path(&req.start)
+                    .and_then(|start| {
+                        Ok((start, renderer.resolve_span_by_element_path(&req.end)?))
+                    });
+                let (Some(start), Some(end)) = (match spans {
+                    Ok(spans) => spans,
+                    Err(e) => {
+                        info!("RenderActor: failed to resolve selection: {}", e);
+                        return false;
+                    }
+                }) else {
+                    info!("RenderActor: selection is not in text");
+                    return false;
+                };
 
-                info!("RenderActor: resolved element paths: {:?}", res);
-                if let Ok(info) = res {
-                    let _ = self
-                        .webview_sender
-                        .send(WebviewActorRequest::CursorPaths(info));
+                let Some(document) = self.document.borrow().clone() else {
+                    return false;
+                };
+                let text = crate::selection::selection_text(&document, &req.start, &req.end)
+                    .unwrap_or_default();
+                let Ok(_) = self
+                    .resolve_sender
+                    .send(TypstActorRequest::SelectionResolve(SelectionSpans {
+                        webview: req.webview,
+                        text,
+                        start,
+                        end,
+                    }))
+                else {
+                    info!("RenderActor: resolve_sender is dropped");
+                    return false;
+                };
+            }
+            RenderActorRequest::ResolveReveal(req) => {
+                if self.webview != Some(req.webview) {
+                    return false;
+                }
+                info!("RenderActor: resolving top element: {:?}", req.path);
+                let spans = match self
+                    .session
+                    .renderer
+                    .resolve_span_by_element_path(&req.path)
+                {
+                    Ok(spans) => spans,
+                    Err(e) => {
+                        info!("RenderActor: failed to resolve top element: {}", e);
+                        return false;
+                    }
+                };
+                if let Some(spans) = spans {
+                    let Ok(_) = self
+                        .resolve_sender
+                        .send(TypstActorRequest::RevealResolve(spans))
+                    else {
+                        info!("RenderActor: resolve_sender is dropped");
+                        return false;
+                    };
                 }
             }
+            RenderActorRequest::ChangeCursorPosition(spans) => {
+                info!("RenderActor: changing cursor position: {:?}", spans);
+
+                let cursors = self.resolve_element_paths(&spans.cursors);
+                let selections = self.resolve_selected_paths(&spans.selections);
+                info!("RenderActor: resolved element paths: {:?}", cursors);
+                let _ = self
+                    .webview_sender
+                    .send(WebviewActorRequest::CursorPaths(cursors));
+                let _ = self
+                    .webview_sender
+                    .send(WebviewActorRequest::SelectionPaths(selections));
+            }
             RenderActorRequest::RenderFullLatest | RenderActorRequest::RenderIncremental => {}
         }
 
@@ -125,15 +274,55 @@ impl RenderActor {
     }
 
     async fn run_instrumented(mut self) {
+        if let Some(session_request) = self.session_request.take() {
+            let Ok(request) = session_request
+                .instrument_await("waiting for webview session")
+                .await
+            else {
+                info!("RenderActor: webview is gone before starting a session");
+                return;
+            };
+            if !self
+                .start_session(request)
+                .instrument_await("starting session")
+                .await
+            {
+                info!("RenderActor: svg_sender is dropped");
+                return;
+            }
+        }
         loop {
             let mut has_full_render = false;
             debug!("RenderActor: waiting for message");
-            match self
-                .mailbox
-                .recv()
-                .instrument_await("waiting for message")
-                .await
-            {
+            let wakeup = tokio::select! {
+                msg = self.mailbox.recv().instrument_await("waiting for message") => Wakeup::Message(msg),
+                permit = self.svg_sender.reserve().instrument_await("waiting for webview to catch up"), if self.needs_snapshot => {
+                    let Ok(_) = permit else {
+                        info!("RenderActor: svg_sender is dropped");
+                        break;
+                    };
+                    Wakeup::CaughtUp
+                }
+                _ = self.svg_sender.closed().instrument_await("waiting for webview to close") => {
+                    info!("RenderActor: svg_sender is dropped");
+                    break;
+                }
+            };
+            let msg = match wakeup {
+                Wakeup::Message(msg) => msg,
+                Wakeup::CaughtUp => {
+                    // the webview has caught up, replace the missed deltas by
+                    // a snapshot of the latest document
+                    info!("RenderActor: webview caught up, sending a snapshot");
+                    self.needs_snapshot = false;
+                    if let Some(frame) = self.snapshot_latest() {
+                        // the capacity is just reserved
+                        let _ = self.svg_sender.try_send(frame);
+                    }
+                    continue;
+                }
+            };
+            match msg {
                 Ok(msg) => {
                     has_full_render |= self
                         .process_message(msg)
@@ -144,8 +333,9 @@ impl RenderActor {
                     info!("RenderActor: no more messages");
                     break;
                 }
-                Err(broadcast::error::RecvError::Lagged(_)) => {
+                Err(broadcast::error::RecvError::Lagged(n)) => {
                     info!("RenderActor: lagged message. Some events are dropped");
+                    LAGGED_EVENTS.with_label_values(&["render"]).inc_by(n);
                 }
             }
             // read the queue to empty
@@ -159,94 +349,283 @@ impl RenderActor {
             // otherwise, we render the incremental changes for only once
             let has_full_render = has_full_render;
             debug!("RenderActor: has_full_render: {}", has_full_render);
-            let Some(document) = self.document.borrow().clone() else {
-                info!("RenderActor: document is not ready");
-                continue;
-            };
-            let data = if has_full_render {
-                if let Some(data) = self.renderer.pack_current() {
-                    data
-                } else {
-                    self.renderer.pack_delta(document)
-                }
-            } else {
-                self.renderer.pack_delta(document)
-            };
-            comemo::evict(30);
-            let Ok(_) = self.svg_sender.send(data) else {
+            if !self.render_latest(has_full_render) {
                 info!("RenderActor: svg_sender is dropped");
                 break;
-            };
+            }
+        }
+        if let Some(sessions) = self.sessions {
+            sessions.detach(self.session);
         }
         info!("RenderActor: exiting")
     }
+
+    /// Starts the session requested by the webview, replaying the frames it
+    /// missed if it resumes a session.
+    ///
+    /// Returns false if the webview is gone.
+    async fn start_session(&mut self, request: SessionRequest) -> bool {
+        let resumed = match request {
+            SessionRequest::New => None,
+            SessionRequest::Resume { token, version } => self
+                .sessions
+                .as_ref()
+                .and_then(|sessions| sessions.resume(&token))
+                .and_then(|session| {
+                    let frames = session.replay_since(version)?;
+                    Some((session, version, frames))
+                }),
+        };
+        let Some((session, version, frames)) = resumed else {
+            // the webview starts from a snapshot, whatever it holds
+            info!("RenderActor: starting session {}", self.session.token);
+            let mut frames = vec![format!("session,{}", self.session.token).into_bytes()];
+            frames.extend(self.snapshot_latest());
+            for frame in frames {
+                if self.svg_sender.send(frame).await.is_err() {
+                    return false;
+                }
+            }
+            return true;
+        };
+
+        info!(
+            "RenderActor: resuming session {} from version {}, replaying {} frames",
+            session.token,
+            version,
+            frames.len()
+        );
+        self.session = session;
+        let session_frame = format!("session,{}", self.session.token).into_bytes();
+        for frame in std::iter::once(session_frame).chain(frames) {
+            if self.svg_sender.send(frame).await.is_err() {
+                return false;
+            }
+        }
+        // renders the changes since the webview disconnected
+        self.render_latest(false)
+    }
+
+    /// Renders the latest document and sends it to the webview.
+    ///
+    /// Returns false if the webview is gone.
+    fn render_latest(&mut self, has_full_render: bool) -> bool {
+        let Some(document) = self.document.borrow().clone() else {
+            info!("RenderActor: document is not ready");
+            return true;
+        };
+        // a webview that missed some deltas gets a snapshot instead, once
+        // it catches up
+        if self.needs_snapshot {
+            debug!("RenderActor: webview is behind, skipping the render");
+            DROPPED_DELTAS.inc();
+            return true;
+        }
+        let kind = if has_full_render {
+            "full"
+        } else {
+            "incremental"
+        };
+        let start = Instant::now();
+        let renderer = &mut self.session.renderer;
+        let data = if has_full_render {
+            if let Some(data) = renderer.pack_current() {
+                data
+            } else {
+                renderer.pack_delta(document)
+            }
+        } else {
+            renderer.pack_delta(document)
+        };
+        RENDER_DURATION
+            .with_label_values(&[self.metrics_label.as_str(), kind])
+            .observe(start.elapsed().as_secs_f64());
+        RENDER_BYTES
+            .with_label_values(&[self.metrics_label.as_str(), kind])
+            .observe(data.len() as f64);
+        comemo::evict(30);
+        let frame = self.session.frame(&data);
+        match self.svg_sender.try_send(frame) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                info!("RenderActor: webview is behind, waiting for it to catch up");
+                DROPPED_DELTAS.inc();
+                self.needs_snapshot = true;
+                return true;
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => return false,
+        }
+        // reports the time to the first delta of a compilation
+        if let Some(info) = self.status.delta_sent() {
+            let _ = self.editor_sender.send(EditorActorRequest::CompileStatus(
+                CompileStatus::CompileSuccess(info),
+            ));
+        }
+        true
+    }
+
+    /// Frames a snapshot of the latest document, see [`pack_snapshot`].
+    fn snapshot_latest(&mut self) -> Option<Vec<u8>> {
+        let document = self.document.borrow().clone()?;
+        let start = Instant::now();
+        let data = pack_snapshot(&mut self.session.renderer, document)?;
+        RENDER_DURATION
+            .with_label_values(&[self.metrics_label.as_str(), "full"])
+            .observe(start.elapsed().as_secs_f64());
+        RENDER_BYTES
+            .with_label_values(&[self.metrics_label.as_str(), "full"])
+            .observe(data.len() as f64);
+        Some(self.session.frame(&data))
+    }
+
+    /// Resolves the element paths of the spans, leaving out the ones which
+    /// are not laid out.
+    fn resolve_element_paths(&mut self, spans: &[SourceSpanOffset]) -> Vec<Vec<ElementPoint>> {
+        let mut paths = Vec::new();
+        for span_offset in spans {
+            match self
+                .session
+                .renderer
+                .resolve_element_paths_by_span(*span_offset)
+            {
+                Ok(res) => paths.extend(res),
+                Err(err) => debug!(
+                    "RenderActor: failed to resolve {:?}: {:#}",
+                    span_offset, err
+                ),
+            }
+        }
+        paths
+    }
+
+    /// Resolves the element paths of the glyphs of the selected text, see
+    /// [`crate::cursor::selected_glyph_paths`].
+    fn resolve_selected_paths(&mut self, selections: &[SelectedText]) -> Vec<Vec<ElementPoint>> {
+        let Some(document) = self.document.borrow().clone() else {
+            return Vec::new();
+        };
+        let renderer = &mut self.session.renderer;
+        let mut paths = Vec::new();
+        for selected in selections {
+            paths.extend(crate::cursor::selected_glyph_paths(
+                &document,
+                selected,
+                |span_offset| match renderer.resolve_element_paths_by_span(span_offset) {
+                    Ok(res) => res,
+                    Err(err) => {
+                        debug!(
+                            "RenderActor: failed to resolve {:?}: {:#}",
+                            span_offset, err
+                        );
+                        Vec::new()
+                    }
+                },
+            ));
+        }
+        paths
+    }
+}
+
+/// Packs the entire document for a webview, as the incremental renderer only
+/// packs what is new to it.
+///
+/// [`IncrSvgDocServer::pack_current`] can't be used for this, as the server
+/// never keeps a view of the document to pack. A fresh renderer packs the
+/// whole document as its first delta instead, which is sent as a `new`
+/// message for the client to reset its document.
+fn pack_snapshot(renderer: &mut IncrSvgDocServer, document: Arc<Document>) -> Option<Vec<u8>> {
+    *renderer = IncrSvgDocServer::default();
+    renderer.set_should_attach_debug_info(true);
+    let delta = renderer.pack_delta(document);
+    let Some(module) = delta.strip_prefix(b"diff-v1,") else {
+        let head = &delta[..delta.len().min(16)];
+        error!(
+            "RenderActor: cannot make a snapshot of a delta starting with {:?}",
+            String::from_utf8_lossy(head)
+        );
+        return None;
+    };
+    Some([b"new,", module].concat())
 }
 
+/// Reports what is derived from each new document, like the outline, to the
+/// editor and the webviews.
 pub struct OutlineRenderActor {
-    signal: broadcast::Receiver<RenderActorRequest>,
     document: watch::Receiver<Option<Arc<Document>>>,
     editor_tx: mpsc::UnboundedSender<EditorActorRequest>,
+    webview_tx: broadcast::Sender<WebviewActorRequest>,
 
     span_interner: SpanInterner,
+    /// The content left out of the counts of the document statistics.
+    stats_exclude: Vec<StatsExclusion>,
 }
 
 impl OutlineRenderActor {
     pub fn new(
-        signal: broadcast::Receiver<RenderActorRequest>,
         document: watch::Receiver<Option<Arc<Document>>>,
         editor_tx: mpsc::UnboundedSender<EditorActorRequest>,
+        webview_tx: broadcast::Sender<WebviewActorRequest>,
         span_interner: SpanInterner,
     ) -> Self {
         Self {
-            signal,
             document,
             editor_tx,
+            webview_tx,
             span_interner,
+            stats_exclude: Vec::new(),
         }
     }
 
-    pub fn spawn(self, peer_addr: String) {
+    pub fn with_stats_exclude(mut self, stats_exclude: Vec<StatsExclusion>) -> Self {
+        self.stats_exclude = stats_exclude;
+        self
+    }
+
+    pub fn spawn(self) {
         std::thread::Builder::new()
             .name("OutlineRenderActor".to_owned())
-            .spawn(move || self.run(&peer_addr))
+            .spawn(move || self.run())
             .unwrap();
     }
 
     #[tokio::main(flavor = "current_thread")]
-    async fn run(self, peer_addr: &str) {
-        let span = format!("outline render actor<{}>", peer_addr);
+    async fn run(self) {
+        let span = "outline render actor".to_owned();
         let root = REGISTRY.lock().await.register(span.clone().into(), span);
         root.instrument(self.run_instrumented()).await;
     }
 
     async fn run_instrumented(mut self) {
+        let mut last: Option<Arc<Document>> = None;
         loop {
-            debug!("OutlineRenderActor: waiting for message");
-            match self
-                .signal
-                .recv()
-                .instrument_await("waiting for message")
-                .await
-            {
-                Ok(msg) => {
-                    debug!("OutlineRenderActor: received message: {:?}", msg);
-                }
-                Err(broadcast::error::RecvError::Closed) => {
-                    info!("OutlineRenderActor: no more messages");
-                    break;
-                }
-                Err(broadcast::error::RecvError::Lagged(_)) => {
-                    info!("OutlineRenderActor: lagged message. Some events are dropped");
-                }
+            debug!("OutlineRenderActor: waiting for document");
+            let changed = self
+                .document
+                .changed()
+                .instrument_await("waiting for document")
+                .await;
+            if changed.is_err() {
+                info!("OutlineRenderActor: no more documents");
+                break;
             }
-            // read the queue to empty
-            while self.signal.try_recv().is_ok() {}
-            // if a full render is requested, we render the latest document
-            // otherwise, we render the incremental changes for only once
-            let Some(document) = self.document.borrow().clone() else {
+            // only the latest document is reported, and only once
+            let Some(document) = self.document.borrow_and_update().clone() else {
                 info!("OutlineRenderActor: document is not ready");
                 continue;
             };
+            if last
+                .as_ref()
+                .is_some_and(|last| Arc::ptr_eq(last, &document))
+            {
+                continue;
+            }
+            last = Some(document.clone());
+            // the spans interned for older documents, e.g. by search and query
+            // results, expire only after a number of new documents
+            self.span_interner
+                .with_writer(|interner| interner.reset())
+                .instrument_await("resetting span interner")
+                .await;
             let data = self.outline(&document).instrument_await("outline").await;
             comemo::evict(30);
             debug!("OutlineRenderActor: sending outline");
@@ -254,17 +633,63 @@ impl OutlineRenderActor {
                 info!("OutlineRenderActor: outline_sender is dropped");
                 break;
             };
+            debug!("OutlineRenderActor: sending labels");
+            let labels = self.labels(&document).instrument_await("labels").await;
+            let _ = self.editor_tx.send(EditorActorRequest::Labels(labels));
+            debug!("OutlineRenderActor: sending citations");
+            let citations = self
+                .citations(&document)
+                .instrument_await("citations")
+                .await;
+            let _ = self
+                .editor_tx
+                .send(EditorActorRequest::Citations(citations));
+            debug!("OutlineRenderActor: sending document stats");
+            let stats = self
+                .document_stats(&document)
+                .instrument_await("document stats")
+                .await;
+            let _ = self
+                .editor_tx
+                .send(EditorActorRequest::DocumentStats(stats));
+            debug!("OutlineRenderActor: sending speaker notes");
+            let notes = crate::speaker_notes::speaker_notes(&document);
+            let _ = self
+                .webview_tx
+                .send(WebviewActorRequest::SpeakerNotes(notes));
         }
         info!("OutlineRenderActor: exiting")
     }
 
     async fn outline(&self, document: &TypstDocument) -> Outline {
+        self.span_interner
+            .with_writer(|interner| crate::outline::outline(interner, document))
+            .instrument_await("generating outline with span interner")
+            .await
+    }
+
+    async fn labels(&self, document: &TypstDocument) -> Labels {
+        // the numbers are displayed before taking the span interner
+        let index = crate::labels::label_index(document);
+        self.span_interner
+            .with_writer(|interner| crate::labels::labels(interner, index))
+            .instrument_await("listing labels with span interner")
+            .await
+    }
+
+    async fn citations(&self, document: &TypstDocument) -> Citations {
+        self.span_interner
+            .with_writer(|interner| crate::citations::citations(interner, document))
+            .instrument_await("listing citations with span interner")
+            .await
+    }
+
+    async fn document_stats(&self, document: &TypstDocument) -> DocumentStats {
         self.span_interner
             .with_writer(|interner| {
-                interner.reset();
-                crate::outline::outline(interner, document)
+                crate::stats::document_stats(interner, document, &self.stats_exclude)
             })
-            .instrument_await("generating outline with span interner")
+            .instrument_await("counting words with span interner")
             .await
     }
 }
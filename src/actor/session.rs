@@ -0,0 +1,322 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, warn};
+use tokio::time::{self, Instant};
+use typst_ts_svg_exporter::IncrSvgDocServer;
+
+/// The number of sessions kept for resuming after their webviews disconnect.
+const MAX_DETACHED_SESSIONS: usize = 8;
+
+/// The size of the frames kept by a session for resuming webviews.
+const HISTORY_BYTES: usize = 16 * 1024 * 1024;
+
+/// The size of the frames kept by all the detached sessions.
+const DETACHED_HISTORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// The time a detached session is kept for its webview to reconnect.
+const DETACHED_SESSION_TTL: Duration = Duration::from_secs(120);
+
+/// The interval at which the expired sessions are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The request of a webview to start receiving render frames.
+#[derive(Debug, Clone)]
+pub enum SessionRequest {
+    /// Starts a new session from scratch.
+    New,
+    /// Resumes the session of the token, in which the webview already holds
+    /// the frames up to the version.
+    Resume { token: String, version: u64 },
+}
+
+impl SessionRequest {
+    /// Parses the arguments of a `resume,<token> <version>` message.
+    pub fn parse_resume(args: &str) -> Option<Self> {
+        let (token, version) = args.trim().split_once(' ')?;
+        Some(Self::Resume {
+            token: token.to_owned(),
+            version: version.trim().parse().ok()?,
+        })
+    }
+}
+
+/// The render frames sent to a webview, which survive reconnections of the
+/// webview.
+///
+/// Each render payload is framed as `delta,<version>,<payload>`, where the
+/// version counts the frames of the session.
+pub struct RenderSession {
+    pub token: String,
+    pub version: u64,
+    /// Boxed to keep the futures of render actors small.
+    pub renderer: Box<IncrSvgDocServer>,
+    history: VecDeque<(u64, Vec<u8>)>,
+    history_bytes: usize,
+}
+
+impl Default for RenderSession {
+    fn default() -> Self {
+        let mut renderer = Box::<IncrSvgDocServer>::default();
+        renderer.set_should_attach_debug_info(true);
+        Self {
+            token: new_token(),
+            version: 0,
+            renderer,
+            history: VecDeque::new(),
+            history_bytes: 0,
+        }
+    }
+}
+
+/// A token no one can guess, as it lets a webview take over a session.
+fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    if let Err(err) = getrandom::getrandom(&mut bytes) {
+        // the session is then resumed only by chance
+        warn!("RenderSession: failed to generate a token: {}", err);
+    }
+    format!("{:032x}", u128::from_le_bytes(bytes))
+}
+
+impl RenderSession {
+    /// Frames a render payload as the next version, and keeps the frame for
+    /// resuming webviews.
+    pub fn frame(&mut self, payload: &[u8]) -> Vec<u8> {
+        self.version += 1;
+        let frame = [format!("delta,{},", self.version).as_bytes(), payload].concat();
+        // frames before a snapshot are useless
+        if payload.starts_with(b"new,") {
+            self.history.clear();
+            self.history_bytes = 0;
+        }
+        self.history_bytes += frame.len();
+        self.history.push_back((self.version, frame.clone()));
+        while self.history_bytes > HISTORY_BYTES {
+            let Some((_, evicted)) = self.history.pop_front() else {
+                break;
+            };
+            self.history_bytes -= evicted.len();
+        }
+        frame
+    }
+
+    /// The frames a webview holding the version misses, if the session still
+    /// has them.
+    pub fn replay_since(&self, version: u64) -> Option<Vec<Vec<u8>>> {
+        if version > self.version {
+            return None;
+        }
+        let frames: Vec<_> = self
+            .history
+            .iter()
+            .filter(|(v, _)| *v > version)
+            .map(|(_, frame)| frame.clone())
+            .collect();
+        let complete = match self.history.iter().find(|(v, _)| *v > version) {
+            Some((v, frame)) => *v == version + 1 || is_snapshot_frame(frame),
+            None => true,
+        };
+        complete.then_some(frames)
+    }
+}
+
+/// Whether the frame is a `delta` frame.
+pub fn is_delta_frame(frame: &[u8]) -> bool {
+    frame.starts_with(b"delta,")
+}
+
+/// Whether the frame is a `delta` frame carrying a full snapshot, which
+/// replaces the frames before it.
+pub fn is_snapshot_frame(frame: &[u8]) -> bool {
+    let Some(rest) = frame.strip_prefix(b"delta,") else {
+        return false;
+    };
+    let Some(comma) = rest.iter().position(|&b| b == b',') else {
+        return false;
+    };
+    rest[comma + 1..].starts_with(b"new,")
+}
+
+/// The sessions whose webviews disconnected, kept for resuming.
+///
+/// A session is kept for [`DETACHED_SESSION_TTL`], and the oldest sessions are
+/// dropped when there are more than [`MAX_DETACHED_SESSIONS`] or when their
+/// frames take more than [`DETACHED_HISTORY_BYTES`].
+///
+/// Cloning the store is cheap, and the clones share the same sessions.
+#[derive(Clone, Default)]
+pub struct RenderSessions {
+    detached: Arc<Mutex<VecDeque<(Instant, RenderSession)>>>,
+}
+
+impl RenderSessions {
+    /// Keeps the session of a disconnected webview, dropping the oldest ones.
+    pub fn detach(&self, session: RenderSession) {
+        let mut detached = self.detached.lock().unwrap();
+        debug!("RenderSessions: detaching session {}", session.token);
+        let now = Instant::now();
+        detached.push_back((now, session));
+        evict(&mut detached, now);
+    }
+
+    /// Takes the session of the token, if it is still kept.
+    pub fn resume(&self, token: &str) -> Option<RenderSession> {
+        let mut detached = self.detached.lock().unwrap();
+        evict(&mut detached, Instant::now());
+        let index = detached.iter().position(|(_, s)| s.token == token)?;
+        detached.remove(index).map(|(_, session)| session)
+    }
+
+    /// Drops the expired sessions every [`SWEEP_INTERVAL`], until the store
+    /// is dropped.
+    pub fn sweep(&self) -> impl Future<Output = ()> {
+        let detached = Arc::downgrade(&self.detached);
+        async move {
+            let mut interval = time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(detached) = detached.upgrade() else {
+                    break;
+                };
+                evict(&mut detached.lock().unwrap(), Instant::now());
+            }
+        }
+    }
+}
+
+/// Drops the sessions detached for too long, then the oldest sessions until
+/// the rest fit in the limits.
+fn evict(detached: &mut VecDeque<(Instant, RenderSession)>, now: Instant) {
+    detached.retain(|(since, session)| {
+        let expired = now.duration_since(*since) > DETACHED_SESSION_TTL;
+        if expired {
+            debug!("RenderSessions: session {} expired", session.token);
+        }
+        !expired
+    });
+    let mut bytes: usize = detached.iter().map(|(_, s)| s.history_bytes).sum();
+    while detached.len() > MAX_DETACHED_SESSIONS || bytes > DETACHED_HISTORY_BYTES {
+        let Some((_, session)) = detached.pop_front() else {
+            break;
+        };
+        debug!("RenderSessions: dropping session {}", session.token);
+        bytes -= session.history_bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(session: &mut RenderSession) -> Vec<u8> {
+        session.frame(b"new,snapshot")
+    }
+
+    fn delta(session: &mut RenderSession) -> Vec<u8> {
+        session.frame(b"diff-v1,delta")
+    }
+
+    #[test]
+    fn test_parse_resume() {
+        let request = SessionRequest::parse_resume(" abc 42 ");
+        assert!(matches!(
+            request,
+            Some(SessionRequest::Resume { token, version: 42 }) if token == "abc"
+        ));
+        assert!(SessionRequest::parse_resume("abc").is_none());
+        assert!(SessionRequest::parse_resume("abc x").is_none());
+        assert!(SessionRequest::parse_resume("abc -1").is_none());
+    }
+
+    #[test]
+    fn test_frame() {
+        let mut session = RenderSession::default();
+        let first = snapshot(&mut session);
+        let second = delta(&mut session);
+        assert_eq!(first, b"delta,1,new,snapshot");
+        assert_eq!(second, b"delta,2,diff-v1,delta");
+        assert!(is_delta_frame(&first) && is_delta_frame(&second));
+        assert!(is_snapshot_frame(&first));
+        assert!(!is_snapshot_frame(&second));
+        assert!(!is_snapshot_frame(b"new,snapshot"));
+        assert!(!is_snapshot_frame(b"delta,1"));
+        assert!(!is_delta_frame(b"jump,1 0 0"));
+    }
+
+    #[test]
+    fn test_token() {
+        let a = RenderSession::default();
+        let b = RenderSession::default();
+        assert_eq!(a.token.len(), 32);
+        assert_ne!(a.token, b.token);
+    }
+
+    #[test]
+    fn test_replay_since() {
+        let mut session = RenderSession::default();
+        delta(&mut session);
+        let snapshot = snapshot(&mut session);
+        let last = delta(&mut session);
+        // the frames before the snapshot are dropped, but the snapshot
+        // replaces them
+        assert_eq!(
+            session.replay_since(0),
+            Some(vec![snapshot.clone(), last.clone()])
+        );
+        assert_eq!(session.replay_since(1), Some(vec![snapshot, last.clone()]));
+        assert_eq!(session.replay_since(2), Some(vec![last]));
+        assert_eq!(session.replay_since(3), Some(vec![]));
+        // the webview holds frames the session never sent
+        assert_eq!(session.replay_since(4), None);
+    }
+
+    #[test]
+    fn test_replay_since_evicted() {
+        let mut session = RenderSession::default();
+        snapshot(&mut session);
+        let large = vec![b'x'; HISTORY_BYTES / 2];
+        session.frame(&large);
+        session.frame(&large);
+        session.frame(&large);
+        // only the last frame fits, and no snapshot replaces the others
+        assert_eq!(session.replay_since(1), None);
+        assert_eq!(session.replay_since(2), None);
+        assert_eq!(session.replay_since(3).map(|frames| frames.len()), Some(1));
+    }
+
+    #[test]
+    fn test_evict() {
+        let now = Instant::now();
+        let mut detached: VecDeque<_> = (0..MAX_DETACHED_SESSIONS + 2)
+            .map(|_| (now, RenderSession::default()))
+            .collect();
+        let kept = detached[2].1.token.clone();
+        evict(&mut detached, now);
+        assert_eq!(detached.len(), MAX_DETACHED_SESSIONS);
+        assert_eq!(detached[0].1.token, kept);
+
+        evict(&mut detached, now + DETACHED_SESSION_TTL);
+        assert_eq!(detached.len(), MAX_DETACHED_SESSIONS);
+        evict(
+            &mut detached,
+            now + DETACHED_SESSION_TTL + Duration::from_secs(1),
+        );
+        assert!(detached.is_empty());
+    }
+
+    #[test]
+    fn test_evict_bytes() {
+        let now = Instant::now();
+        let mut detached = VecDeque::new();
+        for _ in 0..MAX_DETACHED_SESSIONS {
+            let mut session = RenderSession::default();
+            session.frame(&vec![b'x'; HISTORY_BYTES - 64]);
+            detached.push_back((now, session));
+        }
+        evict(&mut detached, now);
+        assert_eq!(detached.len(), DETACHED_HISTORY_BYTES / HISTORY_BYTES);
+    }
+}
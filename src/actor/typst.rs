@@ -1,25 +1,38 @@
+use std::time::Duration;
+
 use await_tree::InstrumentAwait;
 
 use log::{debug, error, info};
 use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
 use typst::syntax::Span;
 use typst_ts_core::debug_loc::{CharPosition, DocumentPosition, SourceLocation, SourceSpanOffset};
 
 use crate::await_tree::REGISTRY;
+use crate::diagnostics::{CompileDiagnostic, DiagnosticSeverity};
+use crate::outline::document_position;
+use crate::selection::{SelectionSpans, SourceRange, TextSelection};
 use crate::{
-    ChangeCursorPositionRequest, EditorServer, MemoryFiles, MemoryFilesShort, SourceFileServer,
-    SrcToDocJumpRequest,
+    ChangeCursorPositionRequest, CursorSpans, EditorServer, MemoryFiles, MemoryFilesShort,
+    QueryRequest, SourceFileServer, SrcToDocJumpRequest,
 };
 
+use super::editor::{EditorActorRequest, Occurrences};
 use super::render::RenderActorRequest;
-use super::{editor::EditorActorRequest, webview::WebviewActorRequest};
+use super::webview::WebviewActorRequest;
 use crate::DocToSrcJumpInfo;
 
 #[derive(Debug)]
 pub enum TypstActorRequest {
     DocToSrcJumpResolve((SourceSpanOffset, SourceSpanOffset)),
+    /// Reveals the source of the element at the top of a webview.
+    RevealResolve((SourceSpanOffset, SourceSpanOffset)),
     ChangeCursorPosition(ChangeCursorPositionRequest),
     SrcToDocJumpResolve(SrcToDocJumpRequest),
+    /// Scrolls the webviews not showing the cursor to it.
+    FollowCursor(ChangeCursorPositionRequest),
+    Query(QueryRequest),
+    SelectionResolve(SelectionSpans),
 
     SyncMemoryFiles(MemoryFiles),
     UpdateMemoryFiles(MemoryFiles),
@@ -34,6 +47,10 @@ pub struct TypstActor<T> {
     editor_conn_sender: mpsc::UnboundedSender<EditorActorRequest>,
     webview_conn_sender: broadcast::Sender<WebviewActorRequest>,
     renderer_sender: broadcast::Sender<RenderActorRequest>,
+
+    /// Memory file updates are held for this long, so that the updates made
+    /// in the meantime are compiled together.
+    debounce: Duration,
 }
 
 type MpScChannel<T> = (mpsc::UnboundedSender<T>, mpsc::UnboundedReceiver<T>);
@@ -66,6 +83,7 @@ impl<T> TypstActor<T> {
         renderer_sender: broadcast::Sender<RenderActorRequest>,
         editor_conn_sender: mpsc::UnboundedSender<EditorActorRequest>,
         webview_conn_sender: broadcast::Sender<WebviewActorRequest>,
+        debounce: Duration,
     ) -> Self {
         Self {
             client,
@@ -73,6 +91,7 @@ impl<T> TypstActor<T> {
             renderer_sender,
             editor_conn_sender,
             webview_conn_sender,
+            debounce,
         }
     }
 }
@@ -88,17 +107,80 @@ impl<T: SourceFileServer + EditorServer> TypstActor<T> {
 
     pub async fn run_instrumented(mut self) {
         debug!("TypstActor: waiting for message");
-        while let Some(mail) = self
-            .mailbox
-            .recv()
-            .instrument_await("waiting for message")
-            .await
-        {
-            self.process_mail(mail)
+        // the coalesced memory file updates and when to flush them
+        let mut pending: Option<(Instant, MemoryFiles)> = None;
+        loop {
+            let mail = match &pending {
+                Some((deadline, _)) => {
+                    match tokio::time::timeout_at(*deadline, self.mailbox.recv())
+                        .instrument_await("debouncing memory files")
+                        .await
+                    {
+                        Ok(mail) => mail,
+                        Err(_) => {
+                            self.flush_memory_files(&mut pending).await;
+                            continue;
+                        }
+                    }
+                }
+                None => {
+                    self.mailbox
+                        .recv()
+                        .instrument_await("waiting for message")
+                        .await
+                }
+            };
+            let Some(mail) = mail else {
+                break;
+            };
+            match mail {
+                TypstActorRequest::UpdateMemoryFiles(m) => match &mut pending {
+                    Some((_, files)) => files.files.extend(m.files),
+                    None => pending = Some((Instant::now() + self.debounce, m)),
+                },
+                mail => {
+                    // keeps the order of the updates and the other requests
+                    self.flush_memory_files(&mut pending).await;
+                    self.process_mail(mail)
+                        .instrument_await("processing mail")
+                        .await;
+                }
+            }
+        }
+        self.flush_memory_files(&mut pending).await;
+        info!("TypstActor: exiting");
+    }
+
+    async fn flush_memory_files(&mut self, pending: &mut Option<(Instant, MemoryFiles)>) {
+        if let Some((_, files)) = pending.take() {
+            self.process_mail(TypstActorRequest::UpdateMemoryFiles(files))
                 .instrument_await("processing mail")
                 .await;
         }
-        info!("TypstActor: exiting");
+    }
+
+    async fn resolve_document_positions(
+        &mut self,
+        filepath: String,
+        line: usize,
+        character: usize,
+    ) -> Vec<DocumentPosition> {
+        let positions = self
+            .client
+            .resolve_document_positions(crate::Location::Src(SourceLocation {
+                filepath,
+                pos: CharPosition {
+                    line,
+                    column: character,
+                },
+            }))
+            .instrument_await("resolve doc positions")
+            .await
+            .map_err(|err| {
+                error!("TypstActor: failed to resolve src to doc jump: {:#}", err);
+            })
+            .unwrap_or_default();
+        positions.into_iter().map(document_position).collect()
     }
 
     async fn process_mail(&mut self, mail: TypstActorRequest) {
@@ -116,9 +198,43 @@ impl<T: SourceFileServer + EditorServer> TypstActor<T> {
                         .send(EditorActorRequest::DocToSrcJump(info));
                 }
             }
+            TypstActorRequest::RevealResolve(span_range) => {
+                debug!("TypstActor: processing reveal: {:?}", span_range);
+                let res = self
+                    .resolve_span_range(span_range)
+                    .instrument_await("resolve span range")
+                    .await;
+
+                if let Some(info) = res {
+                    let _ = self
+                        .editor_conn_sender
+                        .send(EditorActorRequest::RevealRange(info.into()));
+                }
+            }
             TypstActorRequest::ChangeCursorPosition(req) => {
                 debug!("TypstActor: processing src2doc: {:?}", req);
 
+                if !req.ranges.is_empty() {
+                    let res = self
+                        .client
+                        .resolve_cursor_spans(
+                            req.filepath.to_string_lossy().to_string(),
+                            req.ranges,
+                        )
+                        .instrument_await("resolve cursor spans")
+                        .await
+                        .map_err(|err| {
+                            error!("TypstActor: failed to resolve cursor ranges: {:#}", err);
+                        });
+
+                    if let Ok(spans) = res {
+                        let _ = self
+                            .renderer_sender
+                            .send(RenderActorRequest::ChangeCursorPosition(spans));
+                    }
+                    return;
+                }
+
                 let res = self
                     .client
                     .resolve_source_span(crate::Location::Src(SourceLocation {
@@ -139,48 +255,111 @@ impl<T: SourceFileServer + EditorServer> TypstActor<T> {
                 if let Some(info) = res {
                     let _ = self
                         .renderer_sender
-                        .send(RenderActorRequest::ChangeCursorPosition(info));
+                        .send(RenderActorRequest::ChangeCursorPosition(CursorSpans {
+                            cursors: vec![info],
+                            selections: Vec::new(),
+                        }));
                 }
             }
             TypstActorRequest::SrcToDocJumpResolve(req) => {
                 debug!("TypstActor: processing src2doc: {:?}", req);
 
-                // todo: change name to resolve resolve src position
-                let res = self
+                let filepath = req.filepath.to_string_lossy().to_string();
+                let positions = self
+                    .resolve_document_positions(filepath.clone(), req.line, req.character)
+                    .await;
+
+                if !positions.is_empty() {
+                    let _ = self
+                        .webview_conn_sender
+                        .send(WebviewActorRequest::SrcToDocJump(positions.clone()));
+                }
+                let _ = self
+                    .editor_conn_sender
+                    .send(EditorActorRequest::Occurrences(Occurrences {
+                        filepath,
+                        line: req.line,
+                        character: req.character,
+                        positions,
+                    }));
+            }
+            TypstActorRequest::FollowCursor(req) => {
+                debug!("TypstActor: processing follow cursor: {:?}", req);
+
+                let positions = self
+                    .resolve_document_positions(
+                        req.filepath.to_string_lossy().to_string(),
+                        req.line,
+                        req.character,
+                    )
+                    .await;
+                if !positions.is_empty() {
+                    let _ = self
+                        .webview_conn_sender
+                        .send(WebviewActorRequest::FollowCursor(positions));
+                }
+            }
+            TypstActorRequest::Query(req) => {
+                debug!("TypstActor: processing query: {:?}", req);
+
+                let result = self
                     .client
-                    .resolve_document_position(crate::Location::Src(SourceLocation {
-                        filepath: req.filepath.to_string_lossy().to_string(),
-                        pos: CharPosition {
-                            line: req.line,
-                            column: req.character,
-                        },
-                    }))
-                    .instrument_await("resolve doc position")
+                    .query(req.selector)
+                    .instrument_await("query document")
                     .await
-                    .map_err(|err| {
-                        error!("TypstActor: failed to resolve src to doc jump: {:#}", err);
-                    })
-                    .ok()
-                    .flatten();
-                // impl From<TypstPosition> for DocumentPosition {
-                //     fn from(position: TypstPosition) -> Self {
-                //         Self {
-                //             page_no: position.page.into(),
-                //             x: position.point.x.to_pt() as f32,
-                //             y: position.point.y.to_pt() as f32,
-                //         }
-                //     }
-                // }
+                    .unwrap_or_else(|err| {
+                        error!("TypstActor: failed to query document: {:#}", err);
+                        Err(vec![CompileDiagnostic {
+                            severity: DiagnosticSeverity::Error,
+                            message: format!("failed to query document: {err}"),
+                            hints: Vec::new(),
+                            path: None,
+                            start: None,
+                            end: None,
+                        }])
+                    });
 
-                if let Some(info) = res {
+                let _ = self
+                    .editor_conn_sender
+                    .send(EditorActorRequest::QueryResult(req.id, result));
+            }
+            TypstActorRequest::SelectionResolve(selection) => {
+                debug!("TypstActor: processing selection: {:?}", selection);
+                let start = self
+                    .resolve_span_range(selection.start)
+                    .instrument_await("resolve selection start")
+                    .await;
+                let end = self
+                    .resolve_span_range(selection.end)
+                    .instrument_await("resolve selection end")
+                    .await;
+
+                // The selection covers its first and last glyphs, which are
+                // out of order if it is made backwards.
+                let source = match (start, end) {
+                    (Some(start), Some(end)) if start.filepath == end.filepath => {
+                        Some(SourceRange {
+                            filepath: start.filepath,
+                            start: start.start.into_iter().chain(end.start).min(),
+                            end: start.end.into_iter().chain(end.end).max(),
+                        })
+                    }
+                    (Some(e), _) | (None, Some(e)) => Some(e.into()),
+                    (None, None) => None,
+                };
+
+                if let Some(source) = &source {
                     let _ = self
-                        .webview_conn_sender
-                        .send(WebviewActorRequest::SrcToDocJump(DocumentPosition {
-                            page_no: info.page.into(),
-                            x: info.point.x.to_pt() as f32,
-                            y: info.point.y.to_pt() as f32,
-                        }));
+                        .editor_conn_sender
+                        .send(EditorActorRequest::Select(source.clone()));
                 }
+                let _ = self
+                    .webview_conn_sender
+                    .send(WebviewActorRequest::Selection(TextSelection {
+                        webview: selection.webview,
+                        text: selection.text,
+                        source,
+                    }));
             }
             TypstActorRequest::SyncMemoryFiles(m) => {
                 debug!(
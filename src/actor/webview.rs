@@ -1,16 +1,33 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
 use await_tree::InstrumentAwait;
+use flate2::{write::ZlibEncoder, Compression};
 use futures::{SinkExt, StreamExt};
-use log::{info, trace};
+use log::{debug, error, info, trace};
+use serde::Deserialize;
 use tokio::{
     net::TcpStream,
-    sync::{broadcast, mpsc},
+    sync::{broadcast, mpsc, oneshot, watch},
+    task,
+    time::{self, Instant, MissedTickBehavior},
 };
 use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
 use typst_ts_core::debug_loc::{DocumentPosition, ElementPoint};
 
 use crate::{
-    actor::{editor::DocToSrcJumpResolveRequest, render::ResolveSpanRequest},
+    actor::{
+        editor::DocToSrcJumpResolveRequest,
+        render::{ResolveRevealRequest, ResolveSelectionRequest, ResolveSpanRequest},
+        session::{is_delta_frame, is_snapshot_frame, SessionRequest},
+    },
     await_tree::REGISTRY,
+    metrics::{DROPPED_DELTAS, LAGGED_EVENTS},
+    search::DocumentSearch,
+    selection::TextSelection,
+    speaker_notes::SpeakerNotes,
+    CompileStatus, SearchRequest,
 };
 
 use super::{editor::EditorActorRequest, render::RenderActorRequest};
@@ -21,11 +38,54 @@ pub type SrcToDocJumpInfo = DocumentPosition;
 #[derive(Debug, Clone)]
 pub enum WebviewActorRequest {
     ViewportPosition(DocumentPosition),
-    SrcToDocJump(SrcToDocJumpInfo),
+    /// All the positions of a source location, the first one is jumped to.
+    SrcToDocJump(Vec<SrcToDocJumpInfo>),
     // CursorPosition(CursorPosition),
     CursorPaths(Vec<Vec<ElementPoint>>),
+    /// The paths of the characters selected in the editor.
+    SelectionPaths(Vec<Vec<ElementPoint>>),
+    SpeakerNotes(SpeakerNotes),
+    CompileStatus(CompileStatus),
+    Selection(TextSelection),
+    /// The positions of the cursor followed by the webviews, see
+    /// [`FollowCursor`].
+    FollowCursor(Vec<DocumentPosition>),
+}
+
+/// The party driving the slides shown by all following webviews.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlideLeader {
+    Editor,
+    Webview(usize),
 }
 
+/// State of the synchronized slide navigation, shared by the webviews and the
+/// editor.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlideState {
+    /// The current leader, if any webview or the editor owns the slides.
+    pub leader: Option<SlideLeader>,
+    /// The page shown by the leader, starting at 1.
+    pub page_no: usize,
+}
+
+pub type SlideSync = watch::Sender<SlideState>;
+
+/// Whether the webviews follow the cursor of the editor, scrolling to it as
+/// soon as it leaves their visible region. Both the editor and the webviews
+/// toggle it.
+pub type FollowCursor = watch::Sender<bool>;
+
+/// The element paths of the ends of a selection, as sent by `selection`
+/// messages.
+#[derive(Debug, Deserialize)]
+struct SelectionPaths {
+    start: Vec<(u32, u32, String)>,
+    end: Vec<(u32, u32, String)>,
+}
+
+static NEXT_WEBVIEW_ID: AtomicUsize = AtomicUsize::new(0);
+
 fn position_req(
     event: &'static str,
     DocumentPosition { page_no, x, y }: DocumentPosition,
@@ -33,47 +93,169 @@ fn position_req(
     format!("{event},{page_no} {x} {y}")
 }
 
+/// Parses a position sent by a webview as `<page_no> <x> <y>`.
+fn parse_position(position: &str) -> Option<DocumentPosition> {
+    let mut parts = position.split_whitespace();
+    let page_no = parts.next()?.parse().ok()?;
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    Some(DocumentPosition { page_no, x, y })
+}
+
+/// Like [`position_req`], with the positions separated by commas.
+fn positions_req(event: &'static str, positions: Vec<DocumentPosition>) -> String {
+    let positions: Vec<_> = positions
+        .into_iter()
+        .map(|DocumentPosition { page_no, x, y }| format!("{page_no} {x} {y}"))
+        .collect();
+    format!("{event},{}", positions.join(","))
+}
+
 pub struct WebviewActor {
+    id: usize,
     webview_websocket_conn: WebSocketStream<TcpStream>,
-    svg_receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+    svg_receiver: mpsc::Receiver<Vec<u8>>,
     mailbox: broadcast::Receiver<WebviewActorRequest>,
 
     broadcast_sender: broadcast::Sender<WebviewActorRequest>,
     editor_sender: mpsc::UnboundedSender<EditorActorRequest>,
     render_sender: broadcast::Sender<RenderActorRequest>,
+
+    slide_sync: SlideSync,
+    slide_receiver: watch::Receiver<SlideState>,
+    /// Whether the webview negotiated [`DEFLATE_PROTOCOL`].
+    compression: bool,
+    /// Starts the session of the render actor on the first `current` or
+    /// `resume` message.
+    session_request: Option<oneshot::Sender<SessionRequest>>,
+    /// Answers the `search` messages of the webview.
+    search: Option<DocumentSearch>,
+    follow_cursor: FollowCursor,
+    follow_receiver: watch::Receiver<bool>,
+    /// The top left and bottom right positions of the region shown by the
+    /// webview, as last reported by it.
+    visible: Option<(DocumentPosition, DocumentPosition)>,
+}
+
+/// The number of rendered payloads queued for a webview. A webview falling
+/// further behind gets a fresh snapshot instead of the deltas it missed.
+const SVG_QUEUE_CAPACITY: usize = 8;
+
+/// The websocket subprotocol by which a webview asks for compressed render
+/// payloads. A compressed payload is sent as `deflate,<zlib stream>`, where the
+/// stream decompresses to the original `<event>,<payload>` message.
+pub const DEFLATE_PROTOCOL: &str = "typst-preview.deflate";
+
+/// The interval of pings sent to a webview.
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A webview silent for this long, not even answering pings, is considered
+/// gone.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// The time given to a webview to acknowledge closing the connection.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Payloads smaller than this are not worth compressing.
+const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Compresses a render payload, see [`DEFLATE_PROTOCOL`]. A snapshot may take
+/// several megabytes, so it is compressed on a blocking thread.
+async fn deflate_payload(payload: Vec<u8>) -> io::Result<Vec<u8>> {
+    if payload.len() < COMPRESSION_THRESHOLD {
+        return Ok(payload);
+    }
+    task::spawn_blocking(move || {
+        let mut encoder = ZlibEncoder::new(b"deflate,".to_vec(), Compression::default());
+        encoder.write_all(&payload)?;
+        encoder.finish()
+    })
+    .await
+    .map_err(io::Error::other)?
 }
 
 pub struct Channels {
-    pub svg: (
-        mpsc::UnboundedSender<Vec<u8>>,
-        mpsc::UnboundedReceiver<Vec<u8>>,
+    pub svg: (mpsc::Sender<Vec<u8>>, mpsc::Receiver<Vec<u8>>),
+    pub session: (
+        oneshot::Sender<SessionRequest>,
+        oneshot::Receiver<SessionRequest>,
     ),
 }
 
 impl WebviewActor {
     pub fn set_up_channels() -> Channels {
         Channels {
-            svg: mpsc::unbounded_channel(),
+            svg: mpsc::channel(SVG_QUEUE_CAPACITY),
+            session: oneshot::channel(),
         }
     }
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         websocket_conn: WebSocketStream<TcpStream>,
-        svg_receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+        svg_receiver: mpsc::Receiver<Vec<u8>>,
         broadcast_sender: broadcast::Sender<WebviewActorRequest>,
         mailbox: broadcast::Receiver<WebviewActorRequest>,
         editor_sender: mpsc::UnboundedSender<EditorActorRequest>,
         render_sender: broadcast::Sender<RenderActorRequest>,
+        slide_sync: SlideSync,
+        follow_cursor: FollowCursor,
     ) -> Self {
+        let mut slide_receiver = slide_sync.subscribe();
+        // A late joiner catches up with the current leader immediately.
+        if slide_receiver.borrow().leader.is_some() {
+            slide_receiver.mark_changed();
+        }
+        let mut follow_receiver = follow_cursor.subscribe();
+        // and is told that the follow-cursor mode is on
+        if *follow_receiver.borrow() {
+            follow_receiver.mark_changed();
+        }
         Self {
+            id: NEXT_WEBVIEW_ID.fetch_add(1, Ordering::Relaxed),
             webview_websocket_conn: websocket_conn,
             svg_receiver,
             mailbox,
             broadcast_sender,
             editor_sender,
             render_sender,
+            slide_sync,
+            slide_receiver,
+            compression: false,
+            session_request: None,
+            search: None,
+            follow_cursor,
+            follow_receiver,
+            visible: None,
         }
     }
 
+    /// Lets the webview start or resume the session of its render actor, see
+    /// [`super::render::RenderActor::with_sessions`].
+    pub fn with_session_request(
+        mut self,
+        session_request: oneshot::Sender<SessionRequest>,
+    ) -> Self {
+        self.session_request = Some(session_request);
+        self
+    }
+
+    /// The id of the webview, unique among the webviews of the previewer.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Lets the webview search the text of the document.
+    pub fn with_search(mut self, search: DocumentSearch) -> Self {
+        self.search = Some(search);
+        self
+    }
+
+    /// Compresses the render payloads, see [`DEFLATE_PROTOCOL`].
+    pub fn with_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
     pub async fn run(self, peer_addr: String) {
         let span = format!("webview actor<{}>", peer_addr);
         let root = REGISTRY.lock().await.register(span.clone().into(), span);
@@ -81,22 +263,37 @@ impl WebviewActor {
     }
 
     pub async fn run_instrumented(mut self) {
-        loop {
+        let mut last_seen = Instant::now();
+        let mut heartbeat = time::interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+        heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        'serve: loop {
             tokio::select! {
-                Ok(msg) = self.mailbox.recv().instrument_await("waiting for mailbox") => {
+                msg = self.mailbox.recv().instrument_await("waiting for mailbox") => {
+                    let msg = match msg {
+                        Ok(msg) => msg,
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            info!("WebviewActor: lagged message. Some events are dropped");
+                            LAGGED_EVENTS.with_label_values(&["webview"]).inc_by(n);
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            info!("WebviewActor: no more messages");
+                            break;
+                        }
+                    };
                     trace!("WebviewActor: received message from mailbox: {:?}", msg);
                     match msg {
                         WebviewActorRequest::SrcToDocJump(jump_info) => {
-                            let msg = position_req("jump", jump_info);
-                            self.webview_websocket_conn.send(Message::Binary(msg.into_bytes()))
-                            .instrument_await("send SrcToDocJump message to webview")
-                            .await.unwrap();
+                            let msg = positions_req("jump", jump_info);
+                            if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send SrcToDocJump message to webview").await {
+                                break;
+                            }
                         }
                         WebviewActorRequest::ViewportPosition(jump_info) => {
                             let msg = position_req("viewport", jump_info);
-                            self.webview_websocket_conn.send(Message::Binary(msg.into_bytes()))
-                            .instrument_await("send ViewportPosition message to webview")
-                            .await.unwrap();
+                            if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send ViewportPosition message to webview").await {
+                                break;
+                            }
                         }
                         // WebviewActorRequest::CursorPosition(jump_info) => {
                         //     let msg = position_req("cursor", jump_info);
@@ -105,17 +302,116 @@ impl WebviewActor {
                         WebviewActorRequest::CursorPaths(jump_info) => {
                             let json = serde_json::to_string(&jump_info).unwrap();
                             let msg = format!("cursor-paths,{json}");
-                            self.webview_websocket_conn.send(Message::Binary(msg.into_bytes()))
-                            .instrument_await("send CursorPaths message to webview")
-                            .await.unwrap();
+                            if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send CursorPaths message to webview").await {
+                                break;
+                            }
+                        }
+                        WebviewActorRequest::SelectionPaths(paths) => {
+                            let json = serde_json::to_string(&paths).unwrap();
+                            let msg = format!("selection-paths,{json}");
+                            if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send SelectionPaths message to webview").await {
+                                break;
+                            }
+                        }
+                        WebviewActorRequest::CompileStatus(status) => {
+                            let json = serde_json::to_string(&status).unwrap();
+                            let msg = format!("compile-status,{json}");
+                            if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send CompileStatus message to webview").await {
+                                break;
+                            }
+                        }
+                        WebviewActorRequest::Selection(selection) => {
+                            // the selection of another webview
+                            if selection.webview != self.id {
+                                continue;
+                            }
+                            let json = serde_json::to_string(&selection).unwrap();
+                            let msg = format!("selection,{json}");
+                            if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send Selection message to webview").await {
+                                break;
+                            }
+                        }
+                        WebviewActorRequest::FollowCursor(positions) => {
+                            if !*self.follow_cursor.borrow() || positions.iter().any(|position| self.is_visible(position)) {
+                                continue;
+                            }
+                            let msg = position_req("viewport", positions[0]);
+                            if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send FollowCursor message to webview").await {
+                                break;
+                            }
+                        }
+                        WebviewActorRequest::SpeakerNotes(notes) => {
+                            let json = serde_json::to_string(&notes).unwrap();
+                            let msg = format!("speaker-notes,{json}");
+                            if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send SpeakerNotes message to webview").await {
+                                break;
+                            }
                         }
                     }
                 }
+                Ok(()) = self.slide_receiver.changed().instrument_await("waiting for slide change") => {
+                    let state = *self.slide_receiver.borrow_and_update();
+                    trace!("WebviewActor: slide state changed: {:?}", state);
+                    // the leader itself is already showing the page
+                    if state.leader.is_some_and(|leader| leader != SlideLeader::Webview(self.id)) {
+                        let msg = format!("slide-change,{}", state.page_no);
+                        if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send SlideChange message to webview").await {
+                            break;
+                        }
+                    }
+                }
+                Ok(()) = self.follow_receiver.changed().instrument_await("waiting for follow cursor change") => {
+                    let enabled = *self.follow_receiver.borrow_and_update();
+                    let msg = format!("follow-cursor,{enabled}");
+                    if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send FollowCursor state to webview").await {
+                        break;
+                    }
+                }
                 Some(svg) = self.svg_receiver.recv().instrument_await("waiting for renderer") => {
                     trace!("WebviewActor: received svg from renderer");
-                    self.webview_websocket_conn.send(Message::Binary(svg))
-                    .instrument_await("send svg to webview")
-                    .await.unwrap();
+                    let mut queue = vec![svg];
+                    while let Ok(svg) = self.svg_receiver.try_recv() {
+                        queue.push(svg);
+                    }
+                    // a snapshot replaces the deltas queued before it
+                    if let Some(snapshot) = queue.iter().rposition(|frame| is_snapshot_frame(frame)) {
+                        let queued = queue.len();
+                        let mut index = 0;
+                        queue.retain(|frame| {
+                            index += 1;
+                            index > snapshot || !is_delta_frame(frame)
+                        });
+                        let dropped = queued - queue.len();
+                        if dropped > 0 {
+                            debug!("WebviewActor: dropping {} deltas replaced by a snapshot", dropped);
+                            DROPPED_DELTAS.inc_by(dropped as u64);
+                        }
+                    }
+                    for svg in queue {
+                        let svg = if self.compression {
+                            match deflate_payload(svg).instrument_await("compress svg").await {
+                                Ok(svg) => svg,
+                                Err(err) => {
+                                    error!("WebviewActor: failed to compress svg: {}", err);
+                                    break 'serve;
+                                }
+                            }
+                        } else {
+                            svg
+                        };
+                        if !self.send(Message::Binary(svg)).instrument_await("send svg to webview").await {
+                            break 'serve;
+                        }
+                    }
+                }
+                _ = heartbeat.tick().instrument_await("waiting for heartbeat") => {
+                    if last_seen.elapsed() > IDLE_TIMEOUT {
+                        info!("WebviewActor: webview is silent for {:?}, closing", last_seen.elapsed());
+                        break;
+                    }
+                    if !self.send(Message::Ping(Vec::new())).instrument_await("send ping to webview").await {
+                        break;
+                    }
                 }
                 Some(msg) = self.webview_websocket_conn.next().instrument_await("waiting for websocket") => {
                     trace!("WebviewActor: received message from websocket: {:?}", msg);
@@ -123,22 +419,41 @@ impl WebviewActor {
                         info!("WebviewActor: no more messages from websocket: {}", msg.unwrap_err());
                       break;
                     };
-                    let Message::Text(msg) = msg else {
-                        info!("WebviewActor: received non-text message from websocket: {:?}", msg);
-                        let _ = self.webview_websocket_conn.send(Message::Text(format!("Webview Actor: error, received non-text message: {}", msg)))
-                        .instrument_await("send error message to webview")
-                        .await;
-                        break;
+                    last_seen = Instant::now();
+                    let msg = match msg {
+                        Message::Text(msg) => msg,
+                        // answered by the websocket itself
+                        Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+                        Message::Close(_) => {
+                            info!("WebviewActor: webview closed the connection");
+                            break;
+                        }
+                        Message::Binary(_) => {
+                            info!("WebviewActor: received non-text message from websocket: {:?}", msg);
+                            let _ = self.webview_websocket_conn.send(Message::Text(format!("Webview Actor: error, received non-text message: {}", msg)))
+                            .instrument_await("send error message to webview")
+                            .await;
+                            break;
+                        }
                     };
                     if msg == "current" {
-                        self.render_sender.send(RenderActorRequest::RenderFullLatest).unwrap();
+                        if let Some(session_request) = self.session_request.take() {
+                            let _ = session_request.send(SessionRequest::New);
+                        } else {
+                            let _ = self.render_sender.send(RenderActorRequest::RenderFullLatest);
+                        }
+                    } else if let Some(args) = msg.strip_prefix("resume,") {
+                        let request = SessionRequest::parse_resume(args).unwrap_or(SessionRequest::New);
+                        if let Some(session_request) = self.session_request.take() {
+                            let _ = session_request.send(request);
+                        }
                     } else if msg.starts_with("srclocation") {
                         let location = msg.split(' ').nth(1).unwrap();
-                        self.editor_sender.send(EditorActorRequest::DocToSrcJumpResolve(
+                        let _ = self.editor_sender.send(EditorActorRequest::DocToSrcJumpResolve(
                             DocToSrcJumpResolveRequest {
                                 span: location.trim().to_owned(),
                             },
-                        )).unwrap();
+                        ));
                     } else if msg.starts_with("outline-sync") {
                         let location = msg.split(',').nth(1).unwrap();
                         let location = location.split(' ').collect::<Vec::<&str>>();
@@ -147,20 +462,70 @@ impl WebviewActor {
                         let y = location.get(2).map(|s| s.parse().unwrap()).unwrap_or(0.);
                         let pos = DocumentPosition { page_no, x, y };
 
-                        self.broadcast_sender.send(WebviewActorRequest::ViewportPosition(pos)).unwrap();
+                        let _ = self.broadcast_sender.send(WebviewActorRequest::ViewportPosition(pos));
+                    } else if msg.starts_with("slide-lead") {
+                        let lead = msg.split(',').nth(1).map(str::trim) == Some("true");
+                        self.set_slide_lead(lead);
+                    } else if msg.starts_with("slide-change") {
+                        let page_no = msg.split(',').nth(1).and_then(|s| s.trim().parse().ok());
+                        if let Some(page_no) = page_no {
+                            self.change_slide(page_no);
+                        }
+                    } else if let Some(enabled) = msg.strip_prefix("follow-cursor,") {
+                        let enabled = enabled.trim() == "true";
+                        self.follow_cursor.send_if_modified(|state| std::mem::replace(state, enabled) != enabled);
+                    } else if let Some(region) = msg.strip_prefix("visible,") {
+                        let mut positions = region.split(',').map(parse_position);
+                        if let (Some(Some(start)), Some(Some(end))) = (positions.next(), positions.next()) {
+                            self.visible = Some((start, end));
+                        }
+                    } else if let Some(req) = msg.strip_prefix("search,") {
+                        let Ok(req) = serde_json::from_str::<SearchRequest>(req) else {
+                            info!("WebviewActor: invalid search request: {}", req);
+                            continue;
+                        };
+                        let Some(search) = &self.search else {
+                            continue;
+                        };
+                        let result = search.search(&req).instrument_await("search document").await;
+                        let json = serde_json::to_string(&result).unwrap();
+                        let msg = format!("search-result,{json}");
+                        if !self.send(Message::Binary(msg.into_bytes())).instrument_await("send SearchResult message to webview").await {
+                            break;
+                        }
+                    } else if let Some(paths) = msg.strip_prefix("selection,") {
+                        let Ok(paths) = serde_json::from_str::<SelectionPaths>(paths) else {
+                            info!("WebviewActor: invalid selection: {}", paths);
+                            continue;
+                        };
+                        let to_path = |path: Vec<(u32, u32, String)>| path.into_iter().map(ElementPoint::from).collect();
+                        let _ = self.render_sender.send(RenderActorRequest::ResolveSelection(ResolveSelectionRequest {
+                            webview: self.id,
+                            start: to_path(paths.start),
+                            end: to_path(paths.end),
+                        }));
+                    } else if let Some(path) = msg.strip_prefix("top-element,") {
+                        let Ok(path) = serde_json::from_str::<Vec<(u32, u32, String)>>(path) else {
+                            info!("WebviewActor: invalid top element: {}", path);
+                            continue;
+                        };
+                        let _ = self.render_sender.send(RenderActorRequest::ResolveReveal(ResolveRevealRequest {
+                            webview: self.id,
+                            path: path.into_iter().map(ElementPoint::from).collect(),
+                        }));
                     } else if msg.starts_with("srcpath") {
                         let path = msg.split(' ').nth(1).unwrap();
                         let path = serde_json::from_str(path);
                         if let Ok(path) = path {
                             let path: Vec<(u32, u32, String)> = path;
                             let path = path.into_iter().map(ElementPoint::from).collect::<Vec<_>>();
-                            self.render_sender.send(RenderActorRequest::ResolveSpan(ResolveSpanRequest(path))).unwrap();
+                            let _ = self.render_sender.send(RenderActorRequest::ResolveSpan(ResolveSpanRequest(path)));
                         };
                     } else {
                         info!("WebviewActor: received unknown message from websocket: {}", msg);
-                        self.webview_websocket_conn.send(Message::Text(format!("error, received unknown message: {}", msg)))
+                        let _ = self.webview_websocket_conn.send(Message::Text(format!("error, received unknown message: {}", msg)))
                         .instrument_await("send error message to webview")
-                        .await.unwrap();
+                        .await;
                         break;
                     }
                 }
@@ -169,6 +534,67 @@ impl WebviewActor {
                 }
             }
         }
+        self.set_slide_lead(false);
+        // the peer may be gone without closing the connection
+        let _ = time::timeout(CLOSE_TIMEOUT, self.webview_websocket_conn.close(None))
+            .instrument_await("close websocket")
+            .await;
         info!("WebviewActor: exiting");
     }
+
+    /// Whether the position is in the region shown by the webview. The region
+    /// is unknown until the webview reports it.
+    fn is_visible(&self, position: &DocumentPosition) -> bool {
+        let Some((start, end)) = &self.visible else {
+            return false;
+        };
+        let at = (position.page_no, position.y);
+        (start.page_no, start.y) <= at
+            && at <= (end.page_no, end.y)
+            && start.x <= position.x
+            && position.x <= end.x
+    }
+
+    /// Sends a message to the webview.
+    ///
+    /// Returns false if the webview is gone.
+    async fn send(&mut self, msg: Message) -> bool {
+        match self.webview_websocket_conn.send(msg).await {
+            Ok(()) => true,
+            Err(err) => {
+                info!("WebviewActor: failed to send message to webview: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Claims or releases the ownership of the synchronized slides.
+    ///
+    /// Followers are not notified until the new leader reports its page.
+    fn set_slide_lead(&mut self, lead: bool) {
+        let me = SlideLeader::Webview(self.id);
+        self.slide_sync.send_if_modified(|state| {
+            if lead && state.leader != Some(me) {
+                debug!("WebviewActor: webview {} leads the slides", self.id);
+                state.leader = Some(me);
+            } else if !lead && state.leader == Some(me) {
+                debug!("WebviewActor: webview {} releases the slides", self.id);
+                state.leader = None;
+            }
+            false
+        });
+    }
+
+    /// Moves all followers to the page, if this webview leads the slides.
+    fn change_slide(&mut self, page_no: usize) {
+        let me = SlideLeader::Webview(self.id);
+        self.slide_sync.send_if_modified(|state| {
+            if state.leader == Some(me) {
+                state.page_no = page_no;
+                true
+            } else {
+                false
+            }
+        });
+    }
 }
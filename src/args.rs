@@ -15,6 +15,24 @@ pub enum PreviewMode {
     Slide,
 }
 
+/// Content left out of the word and character counts of the document
+/// statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum StatsExclusion {
+    /// Figures, along with their captions
+    #[cfg_attr(feature = "clap", clap(name = "figures"))]
+    Figures,
+
+    /// Footnote entries at the bottom of pages
+    #[cfg_attr(feature = "clap", clap(name = "footnotes"))]
+    Footnotes,
+
+    /// Bibliographies
+    #[cfg_attr(feature = "clap", clap(name = "bibliography"))]
+    Bibliography,
+}
+
 #[cfg(feature = "clap")]
 const ENV_PATH_SEP: char = if cfg!(windows) { ';' } else { ':' };
 
@@ -55,6 +73,31 @@ pub struct PreviewArgs {
     /// the preview.
     #[clap(long, default_value = "never")]
     pub invert_colors: String,
+
+    /// Wait at least this many milliseconds after an edit from the editor
+    /// before compiling, so that the edits made in the meantime are compiled
+    /// together.
+    #[cfg_attr(
+        feature = "clap",
+        clap(long = "compile-debounce", default_value = "0", value_name = "MS")
+    )]
+    pub compile_debounce: u64,
+
+    /// Report an actor as stalled if it is stuck in one await point for
+    /// longer than this many seconds. Set to 0 to disable the watchdog.
+    #[cfg_attr(
+        feature = "clap",
+        clap(long = "stall-threshold", default_value = "10", value_name = "SECS")
+    )]
+    pub stall_threshold: u64,
+
+    /// Leave these out of the word and character counts of the document
+    /// statistics, separated by commas.
+    #[cfg_attr(
+        feature = "clap",
+        clap(long = "stats-exclude", value_name = "KINDS", value_delimiter = ',')
+    )]
+    pub stats_exclude: Vec<StatsExclusion>,
 }
 
 #[derive(Debug, Clone)]
@@ -83,6 +126,12 @@ pub struct CliArguments {
     )]
     pub static_file_host: String,
 
+    /// Time the evaluation and the layout iterations of each compilation, and
+    /// report them along with the compile time. This records every scope the
+    /// compiler enters, which slows compilations down a bit.
+    #[cfg_attr(feature = "clap", clap(long = "phase-timing"))]
+    pub phase_timing: bool,
+
     /// Don't open the preview in the browser after compilation.
     #[cfg_attr(feature = "clap", clap(long = "no-open"))]
     pub dont_open_in_browser: bool,
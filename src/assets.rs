@@ -0,0 +1,96 @@
+use std::path::{Component, Path, PathBuf};
+
+use hyper::{header, Body, Response};
+use log::{debug, info};
+
+/// Serves a file under the project root, so that relative links and
+/// attachments in the preview work in the browser.
+///
+/// Returns `None` if the path doesn't refer to a file under the root. Hidden
+/// files and directories, e.g. `.git`, are never served.
+pub async fn serve_asset(root: &Path, uri_path: &str) -> Option<Response<Body>> {
+    let path = resolve_asset_path(root, uri_path)?;
+    let data = match tokio::fs::read(&path).await {
+        Ok(data) => data,
+        Err(err) => {
+            debug!("failed to read asset {}: {}", path.display(), err);
+            return None;
+        }
+    };
+
+    info!("Serve asset: {}", path.display());
+    let mut res = Response::new(Body::from(data));
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, mime_type(&path).parse().unwrap());
+    Some(res)
+}
+
+/// Resolves the path of the request to a file restricted to the root.
+fn resolve_asset_path(root: &Path, uri_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(uri_path)?;
+
+    let mut path = root.to_path_buf();
+    for component in Path::new(decoded.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(name) if !name.to_string_lossy().starts_with('.') => path.push(name),
+            Component::CurDir => {}
+            // `..`, absolute paths, prefixes and hidden files are rejected
+            _ => return None,
+        }
+    }
+
+    // Symlinks may still point out of the root.
+    let root = root.canonicalize().ok()?;
+    let path = path.canonicalize().ok()?;
+    (path.starts_with(root) && path.is_file()).then_some(path)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" | "typ" | "md" | "csv" | "bib" | "yml" | "yaml" | "toml" | "xml" => {
+            "text/plain; charset=utf-8"
+        }
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
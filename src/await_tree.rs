@@ -1,8 +1,15 @@
 use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::time::Duration;
 
 use await_tree::{Config, Registry};
+use log::{info, warn};
 use once_cell::sync::Lazy;
+use serde::Serialize;
 use tokio::sync::Mutex;
+
+use crate::StatusTracker;
 pub static REGISTRY: Lazy<Mutex<Registry<Cow<'static, str>>>> =
     Lazy::new(|| Mutex::new(Registry::new(Config::default())));
 
@@ -23,3 +30,387 @@ fn get_await_tree_impl(trace: &Registry<Cow<'static, str>>) -> String {
         .map(|(_, tree)| tree.to_string())
         .collect::<String>()
 }
+
+/// A span of an await-tree, with the time elapsed since it was started.
+#[derive(Debug, Clone, Serialize)]
+pub struct AwaitSpan {
+    pub span: String,
+    pub elapsed_ms: f64,
+    /// Whether the actor is polling the span right now, i.e. it is busy in it
+    /// rather than waiting.
+    pub current: bool,
+    pub children: Vec<AwaitSpan>,
+}
+
+/// The await-tree of an actor.
+#[derive(Debug, Clone, Serialize)]
+pub struct ActorAwaitTree {
+    pub actor: String,
+    /// The await point the actor is currently at.
+    pub current: AwaitPoint,
+    pub tree: AwaitSpan,
+    /// The spans which outlived the span they were started in, e.g. futures
+    /// moved out of a branch of a `select!`.
+    pub detached: Vec<AwaitSpan>,
+}
+
+/// An await point of an actor.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AwaitPoint {
+    pub span: String,
+    pub elapsed_ms: f64,
+}
+
+pub async fn get_await_tree_json_async() -> Vec<ActorAwaitTree> {
+    let trace = REGISTRY.lock().await;
+    get_await_tree_json_impl(&trace)
+}
+
+fn get_await_tree_json_impl(trace: &Registry<Cow<'static, str>>) -> Vec<ActorAwaitTree> {
+    let mut res = trace
+        .iter()
+        .filter_map(|(actor, tree)| parse_actor_tree(actor.to_string(), &tree.to_string()))
+        .collect::<Vec<_>>();
+    res.sort_by(|a, b| a.actor.cmp(&b.actor));
+    res
+}
+
+/// Parses the text form of an await-tree, the only one `await_tree::Tree`
+/// exposes, where the detached spans come after the root span.
+fn parse_actor_tree(actor: String, text: &str) -> Option<ActorAwaitTree> {
+    let mut lines = text.lines().peekable();
+    let tree = parse_span(&mut lines)?;
+    let mut detached = Vec::new();
+    while let Some(header) = lines.next() {
+        if !header.starts_with("[Detached") {
+            return None;
+        }
+        detached.push(parse_span(&mut lines)?);
+    }
+    Some(ActorAwaitTree {
+        actor,
+        current: current_await_point(&tree, &detached),
+        tree,
+        detached,
+    })
+}
+
+/// Parses lines like `  waiting for message [1.234s]  <== current` up to the
+/// next detached span.
+fn parse_span<'a>(lines: &mut Peekable<impl Iterator<Item = &'a str>>) -> Option<AwaitSpan> {
+    let mut stack: Vec<(usize, AwaitSpan)> = Vec::new();
+    let mut root = None;
+    while let Some(line) = lines.next_if(|line| !line.starts_with("[Detached")) {
+        let trimmed = line.trim_start_matches(' ');
+        let depth = (line.len() - trimmed.len()) / 2;
+        let (trimmed, current) = match trimmed.strip_suffix("  <== current") {
+            Some(trimmed) => (trimmed, true),
+            None => (trimmed, false),
+        };
+        let (span, elapsed) = trimmed.rsplit_once(" [")?;
+        let elapsed = elapsed.strip_suffix(']')?.trim_start_matches("!!! ");
+        let node = AwaitSpan {
+            span: span.to_owned(),
+            elapsed_ms: parse_elapsed_ms(elapsed)?,
+            current,
+            children: Vec::new(),
+        };
+        while stack.last().is_some_and(|(d, _)| *d >= depth) {
+            let (_, child) = stack.pop().unwrap();
+            match stack.last_mut() {
+                Some((_, parent)) => parent.children.push(child),
+                None => root = Some(child),
+            }
+        }
+        stack.push((depth, node));
+    }
+    while let Some((_, child)) = stack.pop() {
+        match stack.last_mut() {
+            Some((_, parent)) => parent.children.push(child),
+            None => root = Some(child),
+        }
+    }
+    root
+}
+
+/// Parses the debug format of `Duration`, e.g. `1.234s` or `12.000µs`.
+fn parse_elapsed_ms(elapsed: &str) -> Option<f64> {
+    let unit_start = elapsed.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+    let (value, unit) = elapsed.split_at(unit_start);
+    let value: f64 = value.parse().ok()?;
+    let scale = match unit {
+        "s" => 1e3,
+        "ms" => 1.,
+        "µs" => 1e-3,
+        "ns" => 1e-6,
+        _ => return None,
+    };
+    Some(value * scale)
+}
+
+/// The span marked as current, if the actor is busy polling it.
+fn find_current(node: &AwaitSpan) -> Option<&AwaitSpan> {
+    if node.current {
+        return Some(node);
+    }
+    node.children.iter().find_map(find_current)
+}
+
+fn collect_leaves<'a>(node: &'a AwaitSpan, leaves: &mut Vec<&'a AwaitSpan>) {
+    if node.children.is_empty() {
+        leaves.push(node);
+    }
+    for child in &node.children {
+        collect_leaves(child, leaves);
+    }
+}
+
+/// The await point of an actor: the span it is busy in, if any.
+///
+/// An actor which is not polled waits in all its leaf spans at once, e.g.
+/// in the branches of a `select!`, so the one waiting the longest is taken,
+/// unless it only waits for input.
+fn current_await_point(tree: &AwaitSpan, detached: &[AwaitSpan]) -> AwaitPoint {
+    let spans = std::iter::once(tree).chain(detached);
+    let node = spans.clone().find_map(find_current).unwrap_or_else(|| {
+        let mut leaves = Vec::new();
+        for span in spans {
+            collect_leaves(span, &mut leaves);
+        }
+        leaves
+            .into_iter()
+            .max_by(|a, b| {
+                let key = |s: &AwaitSpan| (!is_idle_span(&s.span), s.elapsed_ms);
+                key(a).partial_cmp(&key(b)).unwrap_or(Ordering::Equal)
+            })
+            .unwrap_or(tree)
+    });
+    AwaitPoint {
+        span: node.span.clone(),
+        elapsed_ms: node.elapsed_ms,
+    }
+}
+
+/// An actor stuck in one await point.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stall {
+    pub actor: String,
+    pub span: String,
+    pub elapsed_ms: f64,
+}
+
+/// Actors are expected to stay long in the await points waiting for input.
+fn is_idle_span(span: &str) -> bool {
+    span.starts_with("waiting for")
+}
+
+/// Finds the await points in which actors are stuck for longer than the
+/// threshold.
+///
+/// An actor busy in a span is stuck there, otherwise it is stuck in each of
+/// the leaf spans it waits in for too long.
+pub fn find_stalls(trees: &[ActorAwaitTree], threshold: Duration) -> Vec<Stall> {
+    let threshold_ms = threshold.as_secs_f64() * 1000.;
+    let mut stalls = Vec::new();
+    for tree in trees {
+        let is_stalled =
+            |node: &AwaitSpan| !is_idle_span(&node.span) && node.elapsed_ms > threshold_ms;
+        let stall = |node: &AwaitSpan| Stall {
+            actor: tree.actor.clone(),
+            span: node.span.clone(),
+            elapsed_ms: node.elapsed_ms,
+        };
+
+        let spans = std::iter::once(&tree.tree).chain(&tree.detached);
+        if let Some(node) = spans.clone().find_map(find_current) {
+            if is_stalled(node) {
+                stalls.push(stall(node));
+            }
+            continue;
+        }
+        let mut leaves = Vec::new();
+        // the root span lives as long as the actor
+        for span in tree.tree.children.iter().chain(&tree.detached) {
+            collect_leaves(span, &mut leaves);
+        }
+        stalls.extend(
+            leaves
+                .into_iter()
+                .filter(|node| is_stalled(node))
+                .map(stall),
+        );
+    }
+    stalls
+}
+
+/// Periodically checks the await-trees, and logs and reports the stalled
+/// actors to the status tracker.
+pub(crate) async fn stall_watchdog(threshold: Duration, status: StatusTracker) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut reported: Vec<Stall> = Vec::new();
+    loop {
+        interval.tick().await;
+        let stalls = find_stalls(&get_await_tree_json_async().await, threshold);
+        let is_same = |a: &Stall, b: &Stall| a.actor == b.actor && a.span == b.span;
+        for stall in &stalls {
+            if !reported.iter().any(|r| is_same(r, stall)) {
+                warn!(
+                    "{} is stuck at \"{}\" for {:.0}ms",
+                    stall.actor, stall.span, stall.elapsed_ms
+                );
+            }
+        }
+        for stall in &reported {
+            if !stalls.iter().any(|s| is_same(s, stall)) {
+                info!("{} recovered from \"{}\"", stall.actor, stall.span);
+            }
+        }
+        status.set_stalls(stalls.clone());
+        reported = stalls;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{pending, Future};
+    use std::pin::pin;
+    use std::sync::{Arc, Mutex};
+
+    use await_tree::{Config, InstrumentAwait, Registry};
+    use futures::future::{select, Either};
+
+    use super::*;
+
+    type TestRegistry = Arc<Mutex<Registry<&'static str>>>;
+
+    /// Runs the future as the actor `actor`, whose await-tree the future can
+    /// dump from the registry.
+    async fn run_actor<F: Future>(f: impl FnOnce(TestRegistry) -> F) -> F::Output {
+        let registry = Arc::new(Mutex::new(Registry::new(Config::default())));
+        let root = registry.lock().unwrap().register("actor", "actor");
+        root.instrument(f(registry)).await
+    }
+
+    fn dump(registry: &TestRegistry) -> String {
+        registry.lock().unwrap().get("actor").unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_current_is_the_polled_span() {
+        let text = run_actor(|registry| async move {
+            let busy = async {
+                tokio::time::sleep(Duration::from_millis(40)).await;
+                dump(&registry)
+            }
+            .instrument_await("compile");
+            // started after the busy span, but only waited in
+            let waiting = async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                pending::<()>()
+                    .instrument_await("waiting for message")
+                    .await
+            };
+            match select(pin!(busy), pin!(waiting)).await {
+                Either::Left((text, _)) => text,
+                Either::Right(_) => unreachable!(),
+            }
+        })
+        .await;
+        assert!(text.contains("compile ["), "{text}");
+        assert!(text.contains("  <== current"), "{text}");
+
+        let tree = parse_actor_tree("actor".to_owned(), &text).unwrap();
+        assert_eq!(tree.tree.span, "actor");
+        let spans: Vec<_> = tree.tree.children.iter().map(|c| &c.span).collect();
+        assert_eq!(spans, ["compile", "waiting for message"]);
+        assert!(tree.tree.children[0].current);
+        assert!(!tree.tree.children[1].current);
+        assert_eq!(tree.current.span, "compile");
+        assert!(tree.detached.is_empty());
+
+        let stalls = find_stalls(&[tree.clone()], Duration::ZERO);
+        assert_eq!(stalls.len(), 1);
+        assert_eq!(stalls[0].span, "compile");
+        assert!(find_stalls(&[tree], Duration::from_secs(3600)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_current_of_waiting_actor() {
+        let text = run_actor(|registry| async move {
+            let waiting = pending::<()>().instrument_await("waiting for message");
+            let resolving = tokio::time::sleep(Duration::from_secs(3600))
+                .instrument_await("resolve span")
+                .instrument_await("process message");
+            let dumping = async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                // dumped from outside of the spans of the actor
+                tokio::spawn(async move { dump(&registry) }).await.unwrap()
+            };
+            tokio::select! {
+                _ = waiting => unreachable!(),
+                _ = resolving => unreachable!(),
+                text = dumping => text,
+            }
+        })
+        .await;
+        assert!(!text.contains("<== current"), "{text}");
+
+        let tree = parse_actor_tree("actor".to_owned(), &text).unwrap();
+        let mut spans: Vec<_> = tree.tree.children.iter().map(|c| c.span.as_str()).collect();
+        spans.sort();
+        assert_eq!(spans, ["process message", "waiting for message"]);
+        // the actor waits for input and for the span, which is not idle
+        assert_eq!(tree.current.span, "resolve span");
+        assert!(tree.current.elapsed_ms > 0.);
+
+        let stalls = find_stalls(&[tree], Duration::ZERO);
+        assert_eq!(stalls.len(), 1);
+        assert_eq!(stalls[0].span, "resolve span");
+    }
+
+    #[tokio::test]
+    async fn test_detached_spans() {
+        let text = run_actor(|registry| async move {
+            let mut moved = pin!(pending::<()>().instrument_await("moved"));
+            async {
+                assert!(futures::poll!(moved.as_mut()).is_pending());
+            }
+            .instrument_await("owner")
+            .await;
+            // the clock of the spans is coarse
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            dump(&registry)
+        })
+        .await;
+        assert!(text.contains("[Detached"), "{text}");
+
+        let tree = parse_actor_tree("actor".to_owned(), &text).unwrap();
+        assert!(tree.tree.children.is_empty());
+        assert_eq!(tree.detached.len(), 1);
+        assert_eq!(tree.detached[0].span, "moved");
+        assert_eq!(tree.current.span, "moved");
+
+        let stalls = find_stalls(&[tree], Duration::ZERO);
+        assert_eq!(stalls.len(), 1);
+        assert_eq!(stalls[0].span, "moved");
+    }
+
+    #[test]
+    fn test_parse_elapsed_ms() {
+        assert_eq!(parse_elapsed_ms("1.500s"), Some(1500.));
+        assert_eq!(parse_elapsed_ms("12.000ms"), Some(12.));
+        assert_eq!(parse_elapsed_ms("3.000µs"), Some(0.003));
+        assert_eq!(parse_elapsed_ms("1.000m"), None);
+        assert_eq!(parse_elapsed_ms("s"), None);
+    }
+
+    #[test]
+    fn test_parse_long_span() {
+        let text = "actor [12.000s]\n  compile [!!! 11.000s]  <== current\n";
+        let tree = parse_actor_tree("actor".to_owned(), text).unwrap();
+        assert_eq!(tree.tree.children[0].elapsed_ms, 11000.);
+        assert_eq!(tree.current.span, "compile");
+        assert!(parse_actor_tree("actor".to_owned(), "actor\n").is_none());
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use comemo::Track;
+use serde::Serialize;
+use typst::foundations::{NativeElement, StyleChain};
+use typst::model::{BibliographyElem, CiteGroup};
+use typst_ts_core::debug_loc::DocumentPosition;
+use typst_ts_core::TypstDocument;
+
+use crate::debug_loc::SpanInternerImpl;
+use crate::outline::document_position;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Citations {
+    citations: Vec<CitationItem>,
+    entries: Vec<BibliographyEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CitationItem {
+    /// The key of the cited entry.
+    key: String,
+    /// Span id in hex-format.
+    span: Option<String>,
+    /// The resolved position in the document.
+    position: Option<DocumentPosition>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BibliographyEntry {
+    key: String,
+    title: Option<String>,
+    /// The number of citations of the entry.
+    citations: usize,
+    /// Whether the entry is rendered in the bibliography, which is the case
+    /// for cited entries, or all entries of a `full` bibliography.
+    rendered: bool,
+}
+
+/// Lists the citations of the document and the entries of its bibliographies.
+pub fn citations(interner: &mut SpanInternerImpl, document: &TypstDocument) -> Citations {
+    let introspector = &document.introspector;
+
+    let mut citations = Vec::new();
+    for elem in introspector.query(&CiteGroup::elem().select()).iter() {
+        let group = elem.to_packed::<CiteGroup>().unwrap();
+        let position = elem
+            .location()
+            .map(|loc| document_position(introspector.position(loc)));
+        for cite in group.children.iter() {
+            let span = cite.span();
+            citations.push(CitationItem {
+                key: cite.key.as_str().to_owned(),
+                span: (!span.is_detached()).then(|| interner.intern(span).to_hex()),
+                position,
+            });
+        }
+    }
+
+    let mut counts = HashMap::<&str, usize>::new();
+    for citation in citations.iter() {
+        *counts.entry(&citation.key).or_default() += 1;
+    }
+    let full = introspector
+        .query(&BibliographyElem::elem().select())
+        .iter()
+        .any(|elem| {
+            let bibliography = elem.to_packed::<BibliographyElem>().unwrap();
+            bibliography.full(StyleChain::default())
+        });
+    let entries = BibliographyElem::keys(introspector.track())
+        .into_iter()
+        .map(|(key, title)| {
+            let citations = counts.get(key.as_str()).copied().unwrap_or_default();
+            BibliographyEntry {
+                key: key.to_string(),
+                title: title.map(|title| title.to_string()),
+                citations,
+                rendered: full || citations > 0,
+            }
+        })
+        .collect();
+
+    Citations { citations, entries }
+}
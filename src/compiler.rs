@@ -1,25 +1,33 @@
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use await_tree::InstrumentAwait;
-use log::error;
+use log::{debug, error};
+use serde::Deserialize;
 
 use typst::diag::SourceResult;
 use typst::layout::Position;
 use typst::model::Document;
-use typst::syntax::Span;
+use typst::syntax::{FileId, Source, Span, VirtualPath};
+use typst::World;
 
 use typst_ts_compiler::service::{
     CompileActor, CompileClient as TsCompileClient, CompileExporter, Compiler, WorldExporter,
 };
-use typst_ts_compiler::service::{CompileDriver, CompileMiddleware};
+use typst_ts_compiler::service::{CompileDriver, CompileMiddleware, EntryManager};
 use typst_ts_compiler::vfs::notify::{FileChangeSet, MemoryEvent};
 use typst_ts_core::debug_loc::SourceSpanOffset;
 use typst_ts_core::Error;
 
-use typst_preview::{CompilationHandle, CompileStatus};
+use typst_preview::metrics::COMPILE_DURATION;
+use typst_preview::{
+    cursor_spans, jump_from_cursor, CursorRange, CursorSpans, DocToSrcJumpInfo, Location,
+    QueryResult,
+};
+use typst_preview::{resolve_diagnostics, CompilationHandle, CompileStatus, CompileTiming};
 use typst_preview::{CompileHost, EditorServer, MemoryFiles, MemoryFilesShort, SourceFileServer};
-use typst_preview::{DocToSrcJumpInfo, Location};
 
 pub type CompileService<H> = CompileActor<Reporter<CompileExporter<CompileDriver>, H>>;
 pub type CompileClient<H> = TsCompileClient<CompileService<H>>;
@@ -32,6 +40,10 @@ pub struct CompileServer<H: CompilationHandle> {
 pub struct Reporter<C, H> {
     inner: C,
     cb: H,
+    /// Counts the edits sent by the editor, see [`TypstClient::edits`].
+    edits: Arc<AtomicUsize>,
+    /// Whether the phases of compilations are recorded, see [`phase_timing`].
+    phase_timing: bool,
 }
 
 impl<C: Compiler, H: CompilationHandle> CompileMiddleware for Reporter<C, H> {
@@ -50,19 +62,124 @@ impl<C: Compiler, H: CompilationHandle> CompileMiddleware for Reporter<C, H> {
         env: &mut typst_ts_compiler::service::CompileEnv,
     ) -> SourceResult<Arc<Document>> {
         self.cb.status(CompileStatus::Compiling);
-        match self.inner_mut().compile(env) {
+        let edits = self.edits.load(Ordering::SeqCst);
+        if self.phase_timing {
+            typst_timing::clear();
+        }
+        let start = Instant::now();
+        let res = self.inner_mut().compile(env);
+        let compile_time = start.elapsed();
+        let outcome = if res.is_ok() { "success" } else { "error" };
+        COMPILE_DURATION
+            .with_label_values(&[outcome])
+            .observe(compile_time.as_secs_f64());
+        let timing = CompileTiming {
+            compile_time,
+            ..if self.phase_timing {
+                phase_timing()
+            } else {
+                CompileTiming::default()
+            }
+        };
+        // Typst cannot be interrupted, but the result of a compilation is
+        // abandoned if newer edits arrived meanwhile, since they are compiled
+        // right after it.
+        if self.edits.load(Ordering::SeqCst) != edits {
+            debug!("Reporter: abandon the superseded compilation");
+            // The outcome is still reported, so that the status does not
+            // stay `Compiling` until the next compilation ends.
+            let status = match &res {
+                Ok(doc) => CompileStatus::CompileSuccess(timing.info(doc.pages.len())),
+                Err(err) => {
+                    CompileStatus::CompileError(resolve_diagnostics(self.inner().world(), err))
+                }
+            };
+            self.cb.status(status);
+            return res;
+        }
+        match res {
             Ok(doc) => {
-                self.cb.notify_compile(Ok(doc.clone()));
+                self.cb.notify_compile_with_timing(Ok(doc.clone()), timing);
                 Ok(doc)
             }
             Err(err) => {
-                self.cb.notify_compile(Err(CompileStatus::CompileError));
+                let diagnostics = resolve_diagnostics(self.inner().world(), &err);
+                self.cb.notify_compile_with_timing(
+                    Err(CompileStatus::CompileError(diagnostics)),
+                    timing,
+                );
                 Err(err)
             }
         }
     }
 }
 
+/// Times the phases of the last compilation from the events recorded by
+/// `typst-timing`, which only exports them as JSON.
+fn phase_timing() -> CompileTiming {
+    #[derive(Deserialize)]
+    struct Event {
+        name: String,
+        ph: String,
+        ts: f64,
+    }
+
+    /// Sums up the outermost scopes, since e.g. `eval` is nested in module
+    /// imports.
+    #[derive(Default)]
+    struct Scope {
+        depth: usize,
+        started: f64,
+        total: f64,
+        count: usize,
+    }
+
+    impl Scope {
+        fn record(&mut self, event: &Event) {
+            if event.ph == "B" {
+                if self.depth == 0 {
+                    self.started = event.ts;
+                    self.count += 1;
+                }
+                self.depth += 1;
+            } else if self.depth > 0 {
+                self.depth -= 1;
+                if self.depth == 0 {
+                    self.total += event.ts - self.started;
+                }
+            }
+        }
+
+        fn time(&self) -> Option<Duration> {
+            (self.count > 0).then(|| Duration::from_secs_f64(self.total / 1e6))
+        }
+    }
+
+    let mut buffer = Vec::new();
+    if let Err(err) = typst_timing::export_json(&mut buffer, |_| (String::new(), 0)) {
+        error!("failed to export timing events: {err}");
+        return CompileTiming::default();
+    }
+    let events: Vec<Event> = serde_json::from_slice(&buffer).unwrap_or_default();
+
+    let mut eval = Scope::default();
+    let mut layout = Scope::default();
+    for event in &events {
+        if event.name == "eval" {
+            eval.record(event);
+        } else if event.name.starts_with("typeset (") {
+            layout.record(event);
+        }
+    }
+
+    CompileTiming {
+        eval_time: eval.time(),
+        layout_iterations: (layout.count > 0).then_some(layout.count),
+        layout_time: layout.time(),
+        ..Default::default()
+    }
+}
+
 impl<C: Compiler + WorldExporter, H> WorldExporter for Reporter<C, H> {
     fn export(&mut self, output: Arc<typst::model::Document>) -> SourceResult<()> {
         self.inner.export(output)
@@ -73,18 +190,30 @@ impl<H: CompilationHandle> CompileServer<H> {
     pub fn new(
         compiler_driver: CompileDriver,
         cb: H,
+        phase_timing: bool,
         // renderer_sender: broadcast::Sender<RenderActorRequest>,
         // editor_conn_sender: mpsc::UnboundedSender<EditorActorRequest>,
     ) -> Self {
         // CompileExporter + DynamicLayoutCompiler + WatchDriver
+        // the recorder is global, and only cleared before each compilation
+        if phase_timing {
+            typst_timing::enable();
+        }
         let driver = CompileExporter::new(compiler_driver);
-        let driver = Reporter { inner: driver, cb };
+        let edits = Arc::new(AtomicUsize::new(0));
+        let driver = Reporter {
+            inner: driver,
+            cb,
+            edits: edits.clone(),
+            phase_timing,
+        };
         let inner = CompileActor::new(driver).with_watch(true);
 
         Self {
             inner,
             client: TypstClient {
                 inner: once_cell::sync::OnceCell::new(),
+                edits,
             },
         }
     }
@@ -101,6 +230,9 @@ impl<H: CompilationHandle> CompileServer<H> {
 
 pub struct TypstClient<H: CompilationHandle> {
     inner: once_cell::sync::OnceCell<CompileClient<H>>,
+    /// Counts the edits sent to the compiler, so that a compilation can tell
+    /// whether it is superseded by newer edits.
+    edits: Arc<AtomicUsize>,
 }
 
 impl<H: CompilationHandle> TypstClient<H> {
@@ -121,10 +253,7 @@ impl<H: CompilationHandle> SourceFileServer for TypstClient<H> {
             .await
     }
 
-    async fn resolve_document_position(
-        &mut self,
-        loc: Location,
-    ) -> Result<Option<Position>, Error> {
+    async fn resolve_document_positions(&mut self, loc: Location) -> Result<Vec<Position>, Error> {
         let Location::Src(src_loc) = loc;
 
         let path = Path::new(&src_loc.filepath).to_owned();
@@ -132,11 +261,40 @@ impl<H: CompilationHandle> SourceFileServer for TypstClient<H> {
         let column = src_loc.pos.column;
 
         self.inner()
-            .resolve_src_to_doc_jump(path, line, column)
+            .steal_async(move |this, _| {
+                let Some(doc) = this.document() else {
+                    return Vec::new();
+                };
+                let Some(source) = source_of(this.compiler.world(), &path) else {
+                    return Vec::new();
+                };
+                let Some(cursor) = source.line_column_to_byte(line, column) else {
+                    return Vec::new();
+                };
+
+                jump_from_cursor(&doc, &source, cursor)
+            })
             .instrument_await("resolve src to doc jump")
             .await
     }
 
+    async fn resolve_cursor_spans(
+        &mut self,
+        filepath: String,
+        ranges: Vec<CursorRange>,
+    ) -> Result<CursorSpans, Error> {
+        let path = Path::new(&filepath).to_owned();
+
+        self.inner()
+            .steal_async(move |this, _| {
+                source_of(this.compiler.world(), &path)
+                    .map(|source| cursor_spans(&source, &ranges))
+                    .unwrap_or_default()
+            })
+            .instrument_await("resolve cursor spans")
+            .await
+    }
+
     async fn resolve_source_location(
         &mut self,
         s: Span,
@@ -158,6 +316,25 @@ impl<H: CompilationHandle> SourceFileServer for TypstClient<H> {
                 end: e.end,
             }))
     }
+
+    async fn query(&mut self, selector: String) -> Result<QueryResult, Error> {
+        self.inner()
+            .steal_async(move |this, _| {
+                let document = this.document();
+                typst_preview::query(this.compiler.world(), document.as_deref(), &selector)
+            })
+            .instrument_await("query document")
+            .await
+    }
+}
+
+/// Gets the source of the file in the workspace.
+fn source_of(world: &(impl World + EntryManager), path: &Path) -> Option<Source> {
+    let root = world.workspace_root()?;
+    let relative_path = path.strip_prefix(&root).ok()?;
+
+    let source_id = FileId::new(None, VirtualPath::new(relative_path));
+    world.source(source_id).ok()
 }
 
 impl<H: CompilationHandle> EditorServer for TypstClient<H> {
@@ -179,6 +356,7 @@ impl<H: CompilationHandle> EditorServer for TypstClient<H> {
                 })
                 .collect(),
         );
+        self.edits.fetch_add(1, Ordering::SeqCst);
         self.inner().add_memory_changes(if reset_shadow {
             MemoryEvent::Sync(files)
         } else {
@@ -191,6 +369,7 @@ impl<H: CompilationHandle> EditorServer for TypstClient<H> {
     async fn remove_shadow_files(&mut self, files: MemoryFilesShort) -> Result<(), Error> {
         // todo: is it safe to believe that the path is normalized?
         let files = FileChangeSet::new_removes(files.files.into_iter().map(From::from).collect());
+        self.edits.fetch_add(1, Ordering::SeqCst);
         self.inner().add_memory_changes(MemoryEvent::Update(files));
 
         Ok(())
@@ -0,0 +1,198 @@
+use std::ops::Range;
+
+use typst::syntax::{LinkedNode, Source, Span, SyntaxKind};
+use typst::text::TextItem;
+use typst_ts_core::debug_loc::{ElementPoint, SourceSpanOffset};
+use typst_ts_core::TypstDocument;
+
+use crate::selection::{text_at, ELEMENT_CHAR_INDEX};
+use crate::CursorRange;
+
+/// The most characters highlighted for the selections of a request, the rest
+/// are left out.
+const MAX_SELECTED_CHARS: usize = 2000;
+
+/// The spans of the text at the cursors and in the selections of the editor.
+#[derive(Debug, Clone, Default)]
+pub struct CursorSpans {
+    /// The characters at the cursors, like
+    /// [`crate::SourceFileServer::resolve_source_span`] gives them.
+    pub cursors: Vec<SourceSpanOffset>,
+    /// The text in the selections, by the text node it is in.
+    pub selections: Vec<SelectedText>,
+}
+
+/// A byte range of the text of a text node.
+#[derive(Debug, Clone)]
+pub struct SelectedText {
+    pub span: Span,
+    pub range: Range<usize>,
+}
+
+/// Resolves the ranges of the editor to the spans of the text they touch.
+///
+/// An empty range is a cursor, which resolves to the character after it. Only
+/// text is resolved, as markup is not laid out itself.
+pub fn cursor_spans(source: &Source, ranges: &[CursorRange]) -> CursorSpans {
+    let root = LinkedNode::new(source.root());
+    let mut res = CursorSpans::default();
+    let mut budget = MAX_SELECTED_CHARS;
+    for range in ranges {
+        let Some(range) = range.to_byte_range(source) else {
+            continue;
+        };
+        if range.is_empty() {
+            let Some(node) = root.leaf_at(range.start) else {
+                continue;
+            };
+            if node.kind() == SyntaxKind::Text {
+                res.cursors.push(SourceSpanOffset {
+                    span: node.span(),
+                    offset: range.start.saturating_sub(node.offset()),
+                });
+            }
+            continue;
+        }
+        collect_selected(&root, &range, &mut budget, &mut res.selections);
+    }
+    res
+}
+
+fn collect_selected(
+    node: &LinkedNode,
+    range: &Range<usize>,
+    budget: &mut usize,
+    res: &mut Vec<SelectedText>,
+) {
+    if *budget == 0 || node.offset() >= range.end || node.range().end <= range.start {
+        return;
+    }
+    if node.kind() != SyntaxKind::Text {
+        for child in node.children() {
+            collect_selected(&child, range, budget, res);
+        }
+        return;
+    }
+
+    let start = range.start.saturating_sub(node.offset());
+    let end = (range.end - node.offset()).min(node.text().len());
+    let Some(text) = node.text().get(start..end) else {
+        return;
+    };
+    let end = match text.char_indices().nth(*budget) {
+        Some((offset, _)) => start + offset,
+        None => end,
+    };
+    *budget -= node.text()[start..end].chars().count();
+    res.push(SelectedText {
+        span: node.span(),
+        range: start..end,
+    });
+}
+
+/// Resolves selected text to the element paths of its glyphs.
+///
+/// The `lookup` of an offset gives the paths to the runs of text with its
+/// glyph, and the rest of the selected glyphs in a run are taken from its
+/// frame. So the text is looked up once for each run it is laid out in.
+pub fn selected_glyph_paths(
+    document: &TypstDocument,
+    selected: &SelectedText,
+    mut lookup: impl FnMut(SourceSpanOffset) -> Vec<Vec<ElementPoint>>,
+) -> Vec<Vec<ElementPoint>> {
+    let mut paths = Vec::new();
+    let mut seen: Vec<&TextItem> = Vec::new();
+    let mut offset = selected.range.start;
+    while offset < selected.range.end {
+        // text which is not laid out is skipped a byte at a time
+        let mut next = offset + 1;
+        let found = lookup(SourceSpanOffset {
+            span: selected.span,
+            offset,
+        });
+        for mut path in found {
+            let Some((_, item, _)) = text_at(document, &path) else {
+                continue;
+            };
+            // the last glyph of a run is found for any offset after it
+            if seen.iter().any(|seen| std::ptr::eq(*seen, item)) {
+                continue;
+            }
+            seen.push(item);
+            if path
+                .last()
+                .is_some_and(|point| point.kind == ELEMENT_CHAR_INDEX)
+            {
+                path.pop();
+            }
+            for (i, glyph) in item.glyphs.iter().enumerate() {
+                let (span, start) = (glyph.span.0, glyph.span.1 as usize);
+                if span != selected.span || !selected.range.contains(&start) {
+                    continue;
+                }
+                next = next.max(start + glyph.range().len());
+                let mut path = path.clone();
+                path.push(ElementPoint {
+                    kind: ELEMENT_CHAR_INDEX,
+                    index: i as u32,
+                    fingerprint: String::new(),
+                });
+                paths.push(path);
+            }
+        }
+        offset = next;
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::tests::compile;
+    use crate::selection::tests::{point, text_paths};
+    use crate::selection::{ELEMENT_GROUP, ELEMENT_PAGE};
+
+    #[test]
+    fn test_selected_glyph_paths_across_lines() {
+        let document = compile("#set page(width: 80pt)\nSome words which wrap.");
+        let mut runs = Vec::new();
+        text_paths(
+            &document.pages[0].frame,
+            &mut vec![point(ELEMENT_PAGE, 0), point(ELEMENT_GROUP, 0)],
+            &mut runs,
+        );
+        assert!(runs.len() > 1);
+        let item = |path: &[ElementPoint]| text_at(&document, path).unwrap().1;
+        let span = item(&runs[0]).glyphs[0].span.0;
+
+        // finds the runs with the glyph at an offset, like the renderer
+        let mut lookups = 0;
+        let lookup = |span_offset: SourceSpanOffset| {
+            lookups += 1;
+            let found = runs.iter().filter(|path| {
+                let glyphs = &item(path).glyphs;
+                glyphs.iter().enumerate().any(|(i, glyph)| {
+                    let start = glyph.span.1 as usize;
+                    let end = glyphs
+                        .get(i + 1)
+                        .map_or(usize::MAX, |next| next.span.1 as usize);
+                    (start..end).contains(&span_offset.offset)
+                })
+            });
+            found.cloned().collect()
+        };
+        let selected = SelectedText {
+            span,
+            range: 0.."Some words which wrap.".len(),
+        };
+        let paths = selected_glyph_paths(&document, &selected, lookup);
+
+        let glyphs: usize = runs.iter().map(|path| item(path).glyphs.len()).sum();
+        assert_eq!(paths.len(), glyphs);
+        // once for each run, and once for each space left out at a line break
+        assert!(lookups < 2 * runs.len());
+        assert!(paths
+            .iter()
+            .all(|path| matches!(path.last(), Some(point) if point.kind == ELEMENT_CHAR_INDEX)));
+    }
+}
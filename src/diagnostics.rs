@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use typst::diag::{Severity, SourceDiagnostic};
+use typst::World;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+/// A position in a source file. Both line and column start at 0, and the
+/// column is counted in characters.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DiagnosticPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A compiler diagnostic resolved to its location in the sources.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileDiagnostic {
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    pub hints: Vec<String>,
+    /// The path of the file relative to the project root, or prefixed by the
+    /// package spec for files in packages.
+    pub path: Option<String>,
+    pub start: Option<DiagnosticPosition>,
+    pub end: Option<DiagnosticPosition>,
+}
+
+/// Resolves the diagnostics of a compilation against the world.
+pub fn resolve_diagnostics(
+    world: &dyn World,
+    diagnostics: &[SourceDiagnostic],
+) -> Vec<CompileDiagnostic> {
+    diagnostics
+        .iter()
+        .map(|diag| {
+            let source = diag.span.id().and_then(|id| world.source(id).ok());
+            let path = diag.span.id().map(|id| match id.package() {
+                Some(spec) => format!("{spec}{}", id.vpath().as_rooted_path().display()),
+                None => id.vpath().as_rootless_path().display().to_string(),
+            });
+            let range = source.as_ref().and_then(|source| source.range(diag.span));
+            let position = |offset: usize| {
+                let source = source.as_ref()?;
+                Some(DiagnosticPosition {
+                    line: source.byte_to_line(offset)?,
+                    column: source.byte_to_column(offset)?,
+                })
+            };
+
+            CompileDiagnostic {
+                severity: match diag.severity {
+                    Severity::Error => DiagnosticSeverity::Error,
+                    Severity::Warning => DiagnosticSeverity::Warning,
+                },
+                message: diag.message.to_string(),
+                hints: diag.hints.iter().map(|hint| hint.to_string()).collect(),
+                path,
+                start: range.as_ref().and_then(|range| position(range.start)),
+                end: range.as_ref().and_then(|range| position(range.end)),
+            }
+        })
+        .collect()
+}
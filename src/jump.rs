@@ -0,0 +1,90 @@
+use std::num::NonZeroUsize;
+
+use typst::layout::{Abs, Point, Position, Transform};
+use typst::syntax::{LinkedNode, Source, SyntaxKind};
+use typst_ts_core::TypstDocument;
+
+use crate::search::collect_runs;
+
+/// Finds the positions in the document where the text at the cursor is shown.
+///
+/// Text may be shown several times, e.g. in running headers or in the entries
+/// of an outline, and each time gives a position, at the glyph of the cursor
+/// if it is shown. Text broken into several lines counts once. If the text is
+/// not shown at all, the position of the closest text of the same file is
+/// given instead.
+pub fn jump_from_cursor(document: &TypstDocument, source: &Source, cursor: usize) -> Vec<Position> {
+    let Some(node) = LinkedNode::new(source.root()).leaf_at(cursor) else {
+        return Vec::new();
+    };
+    if node.kind() != SyntaxKind::Text {
+        return Vec::new();
+    }
+    let span = node.span();
+    let offset = cursor.saturating_sub(node.offset());
+    // the glyph of the cursor is the last one starting before it
+    let is_closer = |o: usize, best: usize| o <= offset && (best > offset || o > best);
+
+    let mut positions = Vec::new();
+    let mut closest: Option<(u64, Position)> = None;
+    for (i, page) in document.pages.iter().enumerate() {
+        let page = (NonZeroUsize::new(i + 1).unwrap(), page);
+        let mut runs = Vec::new();
+        collect_runs(&page.1.frame, Transform::identity(), &mut runs);
+
+        // the offset of the glyph of the position of the current occurrence
+        let mut occurrence: Option<usize> = None;
+        for run in runs {
+            let mut x = Abs::zero();
+            let mut found: Option<(usize, Abs)> = None;
+            for glyph in run.item.glyphs.iter() {
+                if glyph.span.0 == span {
+                    let o = glyph.span.1 as usize;
+                    if found.map_or(true, |(best, _)| is_closer(o, best)) {
+                        found = Some((o, x));
+                    }
+                } else if glyph.span.0.id() == span.id() {
+                    let distance = glyph.span.0.number().abs_diff(span.number());
+                    if closest.map_or(true, |(d, _)| distance < d) {
+                        let point = Point::with_x(x).transform(run.ts);
+                        closest = Some((
+                            distance,
+                            Position {
+                                page: page.0,
+                                point,
+                            },
+                        ));
+                    }
+                }
+                x += glyph.x_advance.at(run.item.size);
+            }
+
+            let Some((o, x)) = found else {
+                occurrence = None;
+                continue;
+            };
+            let position = Position {
+                page: page.0,
+                point: Point::with_x(x).transform(run.ts),
+            };
+            match occurrence {
+                // the next line of the same occurrence
+                Some(best) => {
+                    if is_closer(o, best) {
+                        *positions.last_mut().unwrap() = position;
+                        occurrence = Some(o);
+                    }
+                }
+                None => {
+                    positions.push(position);
+                    occurrence = Some(o);
+                }
+            }
+        }
+    }
+
+    if positions.is_empty() {
+        positions.extend(closest.map(|(_, position)| position));
+    }
+    positions
+}
@@ -0,0 +1,294 @@
+use std::collections::HashSet;
+
+use comemo::{Prehashed, Track};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use typst::diag::{FileError, FileResult};
+use typst::engine::{Engine, Route};
+use typst::eval::Tracer;
+use typst::foundations::{
+    Bytes, Content, Context, Datetime, Label, NativeElement, Repr, Selector, StyleChain, Value,
+};
+use typst::introspection::{Introspector, Locator};
+use typst::layout::Position;
+use typst::model::{BibliographyElem, FigureElem, FigureKind, RefElem, Refable};
+use typst::syntax::{FileId, Source, Span};
+use typst::text::{Font, FontBook};
+use typst::{Library, World};
+use typst_ts_core::debug_loc::DocumentPosition;
+use typst_ts_core::TypstDocument;
+
+use crate::debug_loc::SpanInternerImpl;
+use crate::outline::document_position;
+
+/// A labelled element of the document.
+#[derive(Debug, Clone)]
+pub struct LabelEntry {
+    label: Label,
+    kind: String,
+    figure_kind: Option<String>,
+    numbering: Option<String>,
+    span: Span,
+    position: Option<Position>,
+}
+
+/// A reference of the document, e.g. `@intro`.
+#[derive(Debug, Clone)]
+pub struct ReferenceEntry {
+    target: Label,
+    resolved: bool,
+    span: Span,
+    position: Option<Position>,
+}
+
+/// The labels and references of a compiled document.
+#[derive(Debug, Clone, Default)]
+pub struct LabelIndex {
+    labels: Vec<LabelEntry>,
+    references: Vec<ReferenceEntry>,
+}
+
+/// The world in which the numbers of the labelled elements are displayed.
+///
+/// The compiled document keeps no counter states: the introspector only has
+/// the counter updates, which may be functions, and the laid-out numbers
+/// carry no span to find them by. So the numbers are displayed again, like
+/// the elements display them, but the world of the compiler stays with the
+/// compiler, and this one only has the standard library. Hence a numbering
+/// or counter update reading files, fonts, the date or `sys.inputs` is left
+/// out or displayed differently, and contextual numberings see the default
+/// styles, e.g. the default `text.lang`.
+struct NumberingWorld {
+    main: Source,
+}
+
+static LIBRARY: Lazy<Prehashed<Library>> = Lazy::new(|| Prehashed::new(Library::default()));
+
+static FONT_BOOK: Lazy<Prehashed<FontBook>> = Lazy::new(|| Prehashed::new(FontBook::new()));
+
+impl World for NumberingWorld {
+    fn library(&self) -> &Prehashed<Library> {
+        &LIBRARY
+    }
+
+    fn book(&self) -> &Prehashed<FontBook> {
+        &FONT_BOOK
+    }
+
+    fn main(&self) -> Source {
+        self.main.clone()
+    }
+
+    fn source(&self, _id: FileId) -> FileResult<Source> {
+        Err(FileError::AccessDenied)
+    }
+
+    fn file(&self, _id: FileId) -> FileResult<Bytes> {
+        Err(FileError::AccessDenied)
+    }
+
+    fn font(&self, _index: usize) -> Option<Font> {
+        None
+    }
+
+    fn today(&self, _offset: Option<i64>) -> Option<Datetime> {
+        None
+    }
+}
+
+/// Collects the labels and references of the document.
+pub fn label_index(document: &TypstDocument) -> LabelIndex {
+    let world = NumberingWorld {
+        main: Source::detached(""),
+    };
+    let introspector = &document.introspector;
+    let mut locator = Locator::new();
+    let mut tracer = Tracer::new();
+    let mut engine = Engine {
+        world: (&world as &dyn World).track(),
+        introspector: introspector.track(),
+        route: Route::default(),
+        locator: &mut locator,
+        tracer: tracer.track_mut(),
+    };
+
+    let labels = introspector
+        .all()
+        .filter_map(|elem| {
+            Some(LabelEntry {
+                label: elem.label()?,
+                kind: elem.func().name().to_owned(),
+                figure_kind: figure_kind(elem),
+                numbering: numbering(&mut engine, elem),
+                span: elem.span(),
+                position: position(introspector, elem),
+            })
+        })
+        .collect();
+
+    // citations are references to the keys of bibliographies
+    let keys: HashSet<_> = BibliographyElem::keys(introspector.track())
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    let references = introspector
+        .query(&RefElem::elem().select())
+        .iter()
+        .map(|elem| {
+            let target = elem.to_packed::<RefElem>().unwrap().target;
+            ReferenceEntry {
+                target,
+                resolved: !introspector.query(&Selector::Label(target)).is_empty()
+                    || keys.contains(target.as_str()),
+                span: elem.span(),
+                position: position(introspector, elem),
+            }
+        })
+        .collect();
+
+    LabelIndex { labels, references }
+}
+
+fn figure_kind(elem: &Content) -> Option<String> {
+    let figure = elem.to_packed::<FigureElem>()?;
+    let kind = figure.kind(StyleChain::default()).custom()?;
+    Some(match kind {
+        FigureKind::Elem(elem) => elem.name().to_owned(),
+        FigureKind::Name(name) => name.to_string(),
+    })
+}
+
+/// Displays the number of a numbered element, e.g. `1.2` for a heading.
+fn numbering(engine: &mut Engine, elem: &Content) -> Option<String> {
+    let refable = elem.with::<dyn Refable>()?;
+    let numbering = refable.numbering()?;
+    let loc = elem.location()?;
+    let context = Context::new(Some(loc), Some(StyleChain::default()));
+    let number = refable
+        .counter()
+        .at_loc(engine, loc)
+        .and_then(|state| state.display(engine, context.track(), numbering))
+        .ok()?;
+    Some(match number {
+        Value::Str(s) => s.to_string(),
+        Value::Content(content) => content.plain_text().to_string(),
+        other => other.repr().to_string(),
+    })
+}
+
+fn position(introspector: &Introspector, elem: &Content) -> Option<Position> {
+    elem.location().map(|loc| introspector.position(loc))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Labels {
+    labels: Vec<LabelItem>,
+    references: Vec<ReferenceItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct LabelItem {
+    /// The name of the label, without the angle brackets.
+    label: String,
+    /// The name of the element function, e.g. `heading` or `figure`.
+    kind: String,
+    /// The kind of a figure, e.g. `table` or `image`.
+    figure_kind: Option<String>,
+    /// The displayed number of a numbered element.
+    numbering: Option<String>,
+    /// Span id in hex-format.
+    span: Option<String>,
+    /// The resolved position in the document.
+    position: Option<DocumentPosition>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ReferenceItem {
+    /// The referenced label or bibliography key.
+    target: String,
+    /// Whether the target exists in the document.
+    resolved: bool,
+    /// Span id in hex-format.
+    span: Option<String>,
+    /// The resolved position in the document.
+    position: Option<DocumentPosition>,
+}
+
+pub fn labels(interner: &mut SpanInternerImpl, index: LabelIndex) -> Labels {
+    let mut span_id = |span: Span| (!span.is_detached()).then(|| interner.intern(span).to_hex());
+
+    let labels = index
+        .labels
+        .into_iter()
+        .map(|entry| LabelItem {
+            label: entry.label.as_str().to_owned(),
+            kind: entry.kind,
+            figure_kind: entry.figure_kind,
+            numbering: entry.numbering,
+            span: span_id(entry.span),
+            position: entry.position.map(document_position),
+        })
+        .collect();
+    let references = index
+        .references
+        .into_iter()
+        .map(|entry| ReferenceItem {
+            target: entry.target.as_str().to_owned(),
+            resolved: entry.resolved,
+            span: span_id(entry.span),
+            position: entry.position.map(document_position),
+        })
+        .collect();
+
+    Labels { labels, references }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::tests::compile;
+
+    fn numbers(text: &str) -> Vec<(String, Option<String>)> {
+        label_index(&compile(text))
+            .labels
+            .into_iter()
+            .map(|entry| (entry.label.as_str().to_owned(), entry.numbering))
+            .collect()
+    }
+
+    #[test]
+    fn test_numbering_pattern() {
+        let text = "#set heading(numbering: \"1.a\")\n\
+                    = Intro <intro>\n\
+                    == Scope <scope>\n\
+                    #figure([A], caption: [B]) <fig>\n\
+                    = Plain <plain>";
+        assert_eq!(
+            numbers(text),
+            [
+                ("intro".to_owned(), Some("1".to_owned())),
+                ("scope".to_owned(), Some("1.a".to_owned())),
+                ("fig".to_owned(), Some("1".to_owned())),
+                ("plain".to_owned(), Some("2".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_numbering_function() {
+        let text = "#set heading(numbering: (..n) => \"Part \" + str(n.pos().first() * 10))\n\
+                    = Intro <intro>\n\
+                    #counter(heading).update(n => n + 2)\n\
+                    = Outro <outro>\n\
+                    #set heading(numbering: none)\n\
+                    = Plain <plain>";
+        assert_eq!(
+            numbers(text),
+            [
+                ("intro".to_owned(), Some("Part 10".to_owned())),
+                ("outro".to_owned(), Some("Part 40".to_owned())),
+                ("plain".to_owned(), None),
+            ]
+        );
+    }
+}
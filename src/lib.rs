@@ -1,13 +1,32 @@
 mod actor;
 mod args;
 pub mod await_tree;
+mod citations;
+mod cursor;
 mod debug_loc;
+mod diagnostics;
+mod jump;
+mod labels;
+pub mod metrics;
 mod outline;
-
-pub use actor::editor::CompileStatus;
+mod query;
+mod search;
+mod selection;
+mod speaker_notes;
+mod stats;
+mod status;
+
+pub use actor::editor::{CompileInfo, CompileStatus};
+pub use cursor::{cursor_spans, CursorSpans, SelectedText};
+pub use diagnostics::{
+    resolve_diagnostics, CompileDiagnostic, DiagnosticPosition, DiagnosticSeverity,
+};
+pub use jump::jump_from_cursor;
+pub use query::{query, QueryMatch, QueryResult};
+pub use status::{PreviewStatus, StatusTracker};
 use tokio::sync::{broadcast, mpsc, watch};
 
-use std::{collections::HashMap, future::Future, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, future::Future, path::PathBuf, sync::Arc, time::Duration};
 
 use ::await_tree::InstrumentAwait;
 use debug_loc::SpanInterner;
@@ -15,6 +34,8 @@ use futures::SinkExt;
 use log::info;
 use serde::Deserialize;
 use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::header::{HeaderValue, SEC_WEBSOCKET_PROTOCOL};
 use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::WebSocketStream;
 use typst::{layout::Position, syntax::Span};
@@ -30,6 +51,7 @@ pub use args::*;
 
 use crate::actor::editor::EditorActorRequest;
 use crate::actor::render::RenderActorRequest;
+use crate::actor::webview::WebviewActorRequest;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ChangeCursorPositionRequest {
@@ -38,6 +60,28 @@ pub struct ChangeCursorPositionRequest {
     /// fixme: character is 0-based, UTF-16 code unit.
     /// We treat it as UTF-8 now.
     character: usize,
+    /// All the cursors and selections of the editor, highlighted in place of
+    /// the cursor above if any.
+    #[serde(default)]
+    ranges: Vec<CursorRange>,
+}
+
+/// A selection of the editor, or a cursor if it is empty.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CursorRange {
+    /// Row and column of the start.
+    start: (usize, usize),
+    /// Row and column of the end.
+    end: (usize, usize),
+}
+
+impl CursorRange {
+    pub fn to_byte_range(&self, src: &typst::syntax::Source) -> Option<std::ops::Range<usize>> {
+        let start = src.line_column_to_byte(self.start.0, self.start.1)?;
+        let end = src.line_column_to_byte(self.end.0, self.end.1)?;
+        // the selection may be made backwards
+        Some(start.min(end)..start.max(end))
+    }
 }
 
 // JSON.stringify({
@@ -61,6 +105,24 @@ impl SrcToDocJumpRequest {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    /// Echoed in the response, so that the editor can match them up.
+    id: Option<serde_json::Value>,
+    /// A selector in Typst code, e.g. `<label>` or `figure.where(kind: table)`.
+    selector: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchRequest {
+    /// Echoed in the result, so that the requests can be matched up.
+    id: Option<serde_json::Value>,
+    /// The text to search for.
+    query: String,
+    #[serde(default)]
+    case_sensitive: bool,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct MemoryFiles {
     pub files: HashMap<PathBuf, String>,
@@ -72,37 +134,100 @@ pub struct MemoryFilesShort {
     // mtime: Option<u64>,
 }
 
+/// Timing of a compilation, measured by the compiler.
+///
+/// The phases are only timed if the compiler records them, see
+/// `--phase-timing`.
+#[derive(Debug, Clone, Default)]
+pub struct CompileTiming {
+    pub compile_time: Duration,
+    /// Time spent on evaluating the sources, absent if they are cached.
+    pub eval_time: Option<Duration>,
+    /// Number of layout iterations until the introspection converges.
+    pub layout_iterations: Option<usize>,
+    /// Time spent on the layout iterations.
+    pub layout_time: Option<Duration>,
+}
+
+impl CompileTiming {
+    /// The statistics of the compilation of a document with this many pages.
+    pub fn info(&self, page_count: usize) -> CompileInfo {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.;
+        CompileInfo {
+            compile_ms: ms(self.compile_time),
+            eval_ms: self.eval_time.map(ms),
+            layout_iterations: self.layout_iterations,
+            layout_ms: self.layout_time.map(ms),
+            page_count,
+            first_delta_ms: None,
+        }
+    }
+}
+
 pub trait CompilationHandle: Send + 'static {
     fn status(&self, status: CompileStatus);
     fn notify_compile(&self, res: Result<Arc<Document>, CompileStatus>);
+    /// Like [`Self::notify_compile`], along with the timing of the
+    /// compilation, which is dropped by default.
+    fn notify_compile_with_timing(
+        &self,
+        res: Result<Arc<Document>, CompileStatus>,
+        _timing: CompileTiming,
+    ) {
+        self.notify_compile(res)
+    }
 }
 
 pub struct CompilationHandleImpl {
     doc_sender: watch::Sender<Option<Arc<Document>>>,
     editor_tx: mpsc::UnboundedSender<EditorActorRequest>,
     render_tx: broadcast::Sender<RenderActorRequest>,
+    webview_tx: broadcast::Sender<WebviewActorRequest>,
+    status: StatusTracker,
 }
 
 impl CompilationHandle for CompilationHandleImpl {
     fn status(&self, status: CompileStatus) {
+        self.status.compile_status(status.clone());
         self.editor_tx
             .send(EditorActorRequest::CompileStatus(status))
             .unwrap();
     }
 
     fn notify_compile(&self, res: Result<Arc<Document>, CompileStatus>) {
+        self.notify_compile_with_timing(res, CompileTiming::default())
+    }
+
+    fn notify_compile_with_timing(
+        &self,
+        res: Result<Arc<Document>, CompileStatus>,
+        timing: CompileTiming,
+    ) {
+        self.status.compiled(timing.compile_time);
         match res {
             Ok(doc) => {
+                let info = timing.info(doc.pages.len());
+                self.status.compiled_document(info.clone());
+                self.status
+                    .compile_status(CompileStatus::CompileSuccess(info.clone()));
+                // clears the errors shown in webviews
+                let _ = self.webview_tx.send(WebviewActorRequest::CompileStatus(
+                    CompileStatus::CompileSuccess(info.clone()),
+                ));
                 let _ = self.doc_sender.send(Some(doc)); // it is ok to ignore the error here
                                                          // todo: is it right that ignore zero broadcast receiver?
                 let _ = self.render_tx.send(RenderActorRequest::RenderIncremental);
                 self.editor_tx
                     .send(EditorActorRequest::CompileStatus(
-                        CompileStatus::CompileSuccess,
+                        CompileStatus::CompileSuccess(info),
                     ))
                     .unwrap();
             }
             Err(status) => {
+                self.status.compile_status(status.clone());
+                let _ = self
+                    .webview_tx
+                    .send(WebviewActorRequest::CompileStatus(status.clone()));
                 self.editor_tx
                     .send(EditorActorRequest::CompileStatus(status))
                     .unwrap();
@@ -118,7 +243,9 @@ const HTML: &str = include_str!("index.html");
 const HTML: &str = "<html><body>Typst Preview needs to be built with the `embed-html` feature to work!</body></html>";
 
 pub struct Previewer {
-    frontend_html_factory: Box<dyn Fn(PreviewMode) -> ImmutStr>,
+    frontend_html_factory: Box<dyn Fn(PreviewMode, bool) -> ImmutStr>,
+    doc_watcher: watch::Receiver<Option<Arc<Document>>>,
+    status: StatusTracker,
     data_plane_handle: tokio::task::JoinHandle<()>,
     control_plane_handle: tokio::task::JoinHandle<()>,
 }
@@ -126,7 +253,22 @@ pub struct Previewer {
 impl Previewer {
     /// Get the HTML for the frontend by a given preview mode
     pub fn frontend_html(&self, mode: PreviewMode) -> ImmutStr {
-        (self.frontend_html_factory)(mode)
+        (self.frontend_html_factory)(mode, false)
+    }
+
+    /// Get the HTML for the presenter view of a slide preview
+    pub fn presenter_html(&self) -> ImmutStr {
+        (self.frontend_html_factory)(PreviewMode::Slide, true)
+    }
+
+    /// Get a watcher of the latest compiled document.
+    pub fn document_watcher(&self) -> watch::Receiver<Option<Arc<Document>>> {
+        self.doc_watcher.clone()
+    }
+
+    /// Get a tracker of the status of the previewer.
+    pub fn status_tracker(&self) -> StatusTracker {
+        self.status.clone()
     }
 
     /// Join the previewer actors.
@@ -150,11 +292,22 @@ pub trait SourceFileServer {
         async { Ok(None) }
     }
 
-    fn resolve_document_position(
+    /// Resolves every position where the source location is shown in the
+    /// document, in document order.
+    fn resolve_document_positions(
         &mut self,
         _by: Location,
-    ) -> impl Future<Output = Result<Option<Position>, Error>> + Send {
-        async { Ok(None) }
+    ) -> impl Future<Output = Result<Vec<Position>, Error>> + Send {
+        async { Ok(Vec::new()) }
+    }
+
+    /// Resolves the cursors and selections of the editor in a file.
+    fn resolve_cursor_spans(
+        &mut self,
+        _filepath: String,
+        _ranges: Vec<CursorRange>,
+    ) -> impl Future<Output = Result<CursorSpans, Error>> + Send {
+        async { Ok(CursorSpans::default()) }
     }
 
     fn resolve_source_location(
@@ -164,6 +317,13 @@ pub trait SourceFileServer {
     ) -> impl Future<Output = Result<Option<DocToSrcJumpInfo>, Error>> + Send {
         async { Ok(None) }
     }
+
+    fn query(
+        &mut self,
+        _selector: String,
+    ) -> impl Future<Output = Result<QueryResult, Error>> + Send {
+        async { Ok(Ok(Vec::new())) }
+    }
 }
 
 pub trait EditorServer {
@@ -204,13 +364,34 @@ pub async fn preview<T: CompileHost + Send + 'static>(
 
     // Shared resource
     let span_interner = SpanInterner::new();
+    let (slide_sync, _) = watch::channel(actor::webview::SlideState::default());
+    let (follow_cursor, _) = watch::channel(false);
+    let status = StatusTracker::new();
+    if arguments.stall_threshold > 0 {
+        let threshold = std::time::Duration::from_secs(arguments.stall_threshold);
+        tokio::spawn(await_tree::stall_watchdog(threshold, status.clone()));
+    }
 
     // Set callback
     let doc_watcher = watch::channel::<Option<Arc<Document>>>(None);
+    let document_search = search::DocumentSearch::new(doc_watcher.1.clone(), span_interner.clone());
+
+    // Spawns the outline render actor, shared by the editor and all webviews
+    let outline_render_actor = actor::render::OutlineRenderActor::new(
+        doc_watcher.1.clone(),
+        editor_conn.0.clone(),
+        webview_tx.clone(),
+        span_interner.clone(),
+    )
+    .with_stats_exclude(arguments.stats_exclude);
+    outline_render_actor.spawn();
+
     let client = client(CompilationHandleImpl {
         doc_sender: doc_watcher.0,
         editor_tx: editor_conn.0.clone(),
         render_tx: renderer_mailbox.0.clone(),
+        webview_tx: webview_tx.clone(),
+        status: status.clone(),
     });
 
     // Spawns the typst actor
@@ -220,18 +401,25 @@ pub async fn preview<T: CompileHost + Send + 'static>(
         renderer_mailbox.0.clone(),
         editor_conn.0.clone(),
         webview_tx.clone(),
+        Duration::from_millis(arguments.compile_debounce),
     );
     tokio::spawn(typst_actor.run());
 
     log::info!("Previewer: typst actor spawned");
 
+    let doc_receiver = doc_watcher.1.clone();
     let (data_plane_port_tx, data_plane_port_rx) = tokio::sync::oneshot::channel();
     let data_plane_addr = arguments.data_plane_host;
     let data_plane_handle = {
-        let span_interner = span_interner.clone();
         let typst_tx = typst_mailbox.0.clone();
         let webview_tx = webview_tx.clone();
         let renderer_tx = renderer_mailbox.0.clone();
+        let slide_sync = slide_sync.clone();
+        let follow_cursor = follow_cursor.clone();
+        let status = status.clone();
+        let document_search = document_search.clone();
+        let render_sessions = actor::session::RenderSessions::default();
+        tokio::spawn(render_sessions.sweep());
         tokio::spawn(async move {
             // Create the event loop and TCP listener we'll accept connections on.
             let try_socket = TcpListener::bind(&data_plane_addr)
@@ -248,31 +436,54 @@ pub async fn preview<T: CompileHost + Send + 'static>(
                 .instrument_await("accept data plane connection")
                 .await
             {
-                let span_interner = span_interner.clone();
                 let webview_tx = webview_tx.clone();
                 let webview_rx = webview_tx.subscribe();
                 let typst_tx = typst_tx.clone();
                 let peer_addr = stream
                     .peer_addr()
                     .map_or("unknown".to_string(), |addr| addr.to_string());
-                let mut conn = accept_connection(stream)
+                let accepted = accept_connection(stream, true)
                     .instrument_await("accept data plane websocket connection")
                     .await;
+                let (mut conn, compression) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        info!("Websocket handshake with {} failed: {}", peer_addr, err);
+                        continue;
+                    }
+                };
+                let mut greeting = Vec::new();
                 if enable_partial_rendering {
-                    conn.send(Message::Binary("partial-rendering,true".into()))
-                        .instrument_await("send partial-rendering message to webview")
-                        .await
-                        .unwrap();
+                    greeting.push(Message::Binary("partial-rendering,true".into()));
                 }
                 if !invert_colors.is_empty() {
-                    conn.send(Message::Binary(
+                    greeting.push(Message::Binary(
                         format!("invert-colors,{}", invert_colors).into(),
-                    ))
-                    .instrument_await("send invert-colors message to webview")
-                    .await
-                    .unwrap();
+                    ));
+                }
+                // shows the errors to a webview connecting after a failed compilation
+                if let Some(status @ CompileStatus::CompileError(_)) =
+                    status.snapshot().compile_status
+                {
+                    let json = serde_json::to_string(&status).unwrap();
+                    greeting.push(Message::Binary(format!("compile-status,{json}").into()));
                 }
-                let actor::webview::Channels { svg } =
+                // the outline render actor only broadcasts the speaker notes of
+                // new documents, so a late joiner is sent the current ones
+                if let Some(document) = doc_watcher.1.borrow().clone() {
+                    let notes = speaker_notes::speaker_notes(&document);
+                    let json = serde_json::to_string(&notes).unwrap();
+                    greeting.push(Message::Binary(format!("speaker-notes,{json}").into()));
+                }
+                let greeted = conn
+                    .send_all(&mut futures::stream::iter(greeting.into_iter().map(Ok)))
+                    .instrument_await("send greeting messages to webview")
+                    .await;
+                if let Err(err) = greeted {
+                    info!("Failed to greet webview {}: {}", peer_addr, err);
+                    continue;
+                }
+                let actor::webview::Channels { svg, session } =
                     actor::webview::WebviewActor::set_up_channels();
                 let webview_actor = actor::webview::WebviewActor::new(
                     conn,
@@ -281,23 +492,32 @@ pub async fn preview<T: CompileHost + Send + 'static>(
                     webview_rx,
                     editor_conn.0.clone(),
                     renderer_tx.clone(),
-                );
-                tokio::spawn(webview_actor.run(peer_addr.clone()));
+                    slide_sync.clone(),
+                    follow_cursor.clone(),
+                )
+                .with_compression(compression)
+                .with_session_request(session.0)
+                .with_search(document_search.clone());
+                let webview_id = webview_actor.id();
+                status.webview_connected();
+                let webview_status = status.clone();
+                let webview_peer_addr = peer_addr.clone();
+                tokio::spawn(async move {
+                    webview_actor.run(webview_peer_addr).await;
+                    webview_status.webview_disconnected();
+                });
                 let render_actor = actor::render::RenderActor::new(
                     renderer_tx.subscribe(),
                     doc_watcher.1.clone(),
                     typst_tx,
                     svg.0,
-                    webview_tx,
-                );
-                render_actor.spawn(peer_addr.clone());
-                let outline_render_actor = actor::render::OutlineRenderActor::new(
-                    renderer_tx.subscribe(),
-                    doc_watcher.1.clone(),
+                    webview_tx.clone(),
                     editor_conn.0.clone(),
-                    span_interner,
-                );
-                outline_render_actor.spawn(peer_addr);
+                    status.clone(),
+                )
+                .with_sessions(render_sessions.clone(), session.1)
+                .with_webview(webview_id);
+                render_actor.spawn(peer_addr);
             }
         })
     };
@@ -305,8 +525,10 @@ pub async fn preview<T: CompileHost + Send + 'static>(
     let control_plane_addr = arguments.control_plane_host;
     let control_plane_handle = {
         let span_interner = span_interner.clone();
+        let document_search = document_search.clone();
         let typst_tx = typst_mailbox.0.clone();
         let editor_rx = editor_conn.1;
+        let status = status.clone();
         tokio::spawn(async move {
             let try_socket = TcpListener::bind(&control_plane_addr)
                 .instrument_await("bind control plane server")
@@ -321,15 +543,26 @@ pub async fn preview<T: CompileHost + Send + 'static>(
                 .instrument_await("accept control plane connection")
                 .await
                 .unwrap();
-            let conn = accept_connection(stream)
+            let (conn, _) = accept_connection(stream, false)
                 .instrument_await("accept control plane websocket connection")
-                .await;
-            let editor_actor =
-                EditorActor::new(editor_rx, conn, typst_tx, webview_tx, span_interner);
+                .await
+                .expect("Error during the websocket handshake occurred");
+            status.set_editor_attached(true);
+            let editor_actor = EditorActor::new(
+                editor_rx,
+                conn,
+                typst_tx,
+                webview_tx,
+                slide_sync,
+                span_interner,
+                document_search,
+                follow_cursor,
+            );
             editor_actor
                 .run()
                 .instrument_await("run editor actor")
                 .await;
+            status.set_editor_attached(false);
         })
     };
     let data_plane_port = data_plane_port_rx.await.unwrap();
@@ -338,36 +571,68 @@ pub async fn preview<T: CompileHost + Send + 'static>(
         format!("ws://127.0.0.1:{data_plane_port}").as_str(),
     );
     // previewMode
-    let frontend_html_factory = Box::new(move |mode| -> ImmutStr {
+    let frontend_html_factory = Box::new(move |mode, presenter| -> ImmutStr {
         let mode = match mode {
             PreviewMode::Document => "Doc",
             PreviewMode::Slide => "Slide",
         };
+        let role = if presenter { "Presenter" } else { "Audience" };
         html.replace(
             "preview-arg:previewMode:Doc",
             format!("preview-arg:previewMode:{}", mode).as_str(),
         )
+        .replace(
+            "preview-arg:role:Audience",
+            format!("preview-arg:role:{}", role).as_str(),
+        )
         .into()
     });
 
     Previewer {
         frontend_html_factory,
+        doc_watcher: doc_receiver,
+        status,
         data_plane_handle,
         control_plane_handle,
     }
 }
 
-async fn accept_connection(stream: TcpStream) -> WebSocketStream<TcpStream> {
-    let addr = stream
-        .peer_addr()
-        .expect("connected streams should have a peer address");
+/// Accepts a websocket connection. If `offer_compression` is set, the peer may
+/// ask for compressed render payloads by the websocket subprotocol
+/// [`actor::webview::DEFLATE_PROTOCOL`], which is returned as agreed.
+async fn accept_connection(
+    stream: TcpStream,
+    offer_compression: bool,
+) -> Result<(WebSocketStream<TcpStream>, bool), tokio_tungstenite::tungstenite::Error> {
+    let addr = stream.peer_addr()?;
     info!("Peer address: {}", addr);
 
-    let ws_stream = tokio_tungstenite::accept_async(stream)
+    let mut compression = false;
+    let negotiate = |req: &Request, mut res: Response| {
+        let mut protocols = req
+            .headers()
+            .get_all(SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        if offer_compression
+            && protocols.any(|protocol| protocol.trim() == actor::webview::DEFLATE_PROTOCOL)
+        {
+            compression = true;
+            res.headers_mut().insert(
+                SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_static(actor::webview::DEFLATE_PROTOCOL),
+            );
+        }
+        Ok(res)
+    };
+    let ws_stream = tokio_tungstenite::accept_hdr_async(stream, negotiate)
         .instrument_await("accept websocket connection")
-        .await
-        .expect("Error during the websocket handshake occurred");
+        .await?;
 
-    info!("New WebSocket connection: {}", addr);
-    ws_stream
+    info!(
+        "New WebSocket connection: {} (compression: {})",
+        addr, compression
+    );
+    Ok((ws_stream, compression))
 }
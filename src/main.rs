@@ -1,4 +1,4 @@
-use std::{borrow::Cow, net::SocketAddr};
+use std::{borrow::Cow, net::SocketAddr, path::PathBuf, sync::Arc};
 
 use await_tree::InstrumentAwait;
 use clap::Parser;
@@ -9,7 +9,9 @@ use typst_ts_compiler::service::CompileDriver;
 use typst_ts_compiler::TypstSystemWorld;
 use typst_ts_core::config::{compiler::EntryOpts, CompileOpts};
 
+use crate::assets::serve_asset;
 use crate::compiler::CompileServer;
+use crate::thumbnail::{parse_page_request, serve_page};
 
 use hyper::{
     service::{make_service_fn, service_fn},
@@ -17,32 +19,94 @@ use hyper::{
 };
 
 use typst_preview::{
-    await_tree::{get_await_tree_async, REGISTRY},
+    await_tree::{get_await_tree_async, get_await_tree_json_async, REGISTRY},
+    metrics::{gather_metrics, METRICS_CONTENT_TYPE},
     preview, CliArguments, PreviewMode, Previewer,
 };
 
+mod assets;
 mod compiler;
+mod thumbnail;
 
 pub fn make_static_host(
     previewer: &Previewer,
     static_file_addr: String,
     mode: PreviewMode,
+    root: PathBuf,
 ) -> (SocketAddr, tokio::task::JoinHandle<()>) {
+    let root: Arc<PathBuf> = Arc::new(root);
     let frontend_html = previewer.frontend_html(mode);
+    let presenter_html = previewer.presenter_html();
+    let doc_watcher = previewer.document_watcher();
+    let status = previewer.status_tracker();
     let make_service = make_service_fn(move |_| {
         let html = frontend_html.clone();
+        let presenter_html = presenter_html.clone();
+        let doc_watcher = doc_watcher.clone();
+        let status = status.clone();
+        let root = root.clone();
         async move {
             Ok::<_, hyper::http::Error>(service_fn(move |req| {
                 // todo: clone may not be necessary
                 let html = html.as_ref().to_owned();
+                let presenter_html = presenter_html.as_ref().to_owned();
+                let document = doc_watcher.borrow().clone();
+                let status = status.clone();
+                let root = root.clone();
                 async move {
                     if req.uri().path() == "/" {
                         log::info!("Serve frontend: {:?}", mode);
                         Ok::<_, Error>(hyper::Response::new(hyper::Body::from(html)))
+                    } else if req.uri().path() == "/presenter" {
+                        log::info!("Serve presenter frontend");
+                        Ok::<_, Error>(hyper::Response::new(hyper::Body::from(presenter_html)))
+                    } else if let Some((page_no, format)) =
+                        parse_page_request(req.uri().path(), req.uri().query())
+                    {
+                        Ok::<_, Error>(serve_page(document, page_no, format).await)
+                    } else if req.uri().path() == "/status" {
+                        let mut res = hyper::Response::new(hyper::Body::from(
+                            serde_json::to_string(&status.snapshot()).unwrap(),
+                        ));
+                        res.headers_mut().insert(
+                            hyper::header::CONTENT_TYPE,
+                            hyper::header::HeaderValue::from_static("application/json"),
+                        );
+                        Ok(res)
+                    } else if req.uri().path() == "/healthz" {
+                        Ok(hyper::Response::new(hyper::Body::from("ok")))
+                    } else if req.uri().path() == "/readyz" {
+                        // ready once a document is compiled and can be previewed
+                        let mut res = hyper::Response::new(hyper::Body::empty());
+                        if status.is_ready() {
+                            *res.body_mut() = hyper::Body::from("ok");
+                        } else {
+                            *res.body_mut() = hyper::Body::from("document is not ready");
+                            *res.status_mut() = hyper::StatusCode::SERVICE_UNAVAILABLE;
+                        }
+                        Ok(res)
+                    } else if req.uri().path() == "/metrics" {
+                        let mut res = hyper::Response::new(hyper::Body::from(gather_metrics()));
+                        res.headers_mut().insert(
+                            hyper::header::CONTENT_TYPE,
+                            hyper::header::HeaderValue::from_static(METRICS_CONTENT_TYPE),
+                        );
+                        Ok(res)
+                    } else if req.uri().path() == "/await_tree.json" {
+                        let mut res = hyper::Response::new(hyper::Body::from(
+                            serde_json::to_string(&get_await_tree_json_async().await).unwrap(),
+                        ));
+                        res.headers_mut().insert(
+                            hyper::header::CONTENT_TYPE,
+                            hyper::header::HeaderValue::from_static("application/json"),
+                        );
+                        Ok(res)
                     } else if req.uri().path() == "/await_tree" {
                         Ok::<_, Error>(hyper::Response::new(hyper::Body::from(
                             get_await_tree_async().await,
                         )))
+                    } else if let Some(res) = serve_asset(&root, req.uri().path()).await {
+                        Ok(res)
                     } else {
                         // jump to /
                         let mut res = hyper::Response::new(hyper::Body::empty());
@@ -130,10 +194,11 @@ async fn main() {
         std::process::exit(0);
     });
 
+    let phase_timing = arguments.phase_timing;
     let previewer = preview(
         arguments.preview,
         move |handle| {
-            let compile_server = CompileServer::new(compiler_driver, handle);
+            let compile_server = CompileServer::new(compiler_driver, handle, phase_timing);
 
             compile_server.spawn().unwrap()
         },
@@ -147,7 +212,7 @@ async fn main() {
     let static_file_addr = arguments.static_file_host;
     let mode = arguments.preview_mode;
     let (static_server_addr, static_server_handle) =
-        make_static_host(&previewer, static_file_addr, mode);
+        make_static_host(&previewer, static_file_addr, mode, root);
     info!("Static file server listening on: {}", static_server_addr);
     if !arguments.dont_open_in_browser {
         if let Err(e) = open::that_detached(format!("http://{}", static_server_addr)) {
@@ -0,0 +1,126 @@
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    Encoder, HistogramVec, IntCounter, IntCounterVec, TextEncoder,
+};
+
+/// Compile durations, labeled by the outcome (`success` or `error`).
+pub static COMPILE_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "typst_preview_compile_duration_seconds",
+        "Duration of compilations",
+        &["outcome"],
+        exponential_buckets(0.005, 2.0, 14).unwrap()
+    )
+    .unwrap()
+});
+
+/// Render durations of a render actor, labeled by its [`ConnectionLabel`] and
+/// the kind (`full` or `incremental`).
+pub static RENDER_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "typst_preview_render_duration_seconds",
+        "Duration of packing a rendered document for a webview",
+        &["connection", "kind"],
+        exponential_buckets(0.001, 2.0, 14).unwrap()
+    )
+    .unwrap()
+});
+
+/// Sizes of the payloads sent by a render actor, labeled by its
+/// [`ConnectionLabel`] and the kind (`full` or `incremental`).
+pub static RENDER_BYTES: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "typst_preview_render_bytes",
+        "Size of the rendered payloads sent to a webview",
+        &["connection", "kind"],
+        exponential_buckets(1024.0, 4.0, 10).unwrap()
+    )
+    .unwrap()
+});
+
+/// The most render actors told apart by the render metrics at once, the
+/// others share the `other` label so that the number of series stays bounded.
+const MAX_CONNECTION_LABELS: usize = 16;
+
+/// Whether each connection label is taken by a live render actor.
+static CONNECTION_SLOTS: Lazy<Mutex<[bool; MAX_CONNECTION_LABELS]>> =
+    Lazy::new(|| Mutex::new([false; MAX_CONNECTION_LABELS]));
+
+/// The `connection` label of the render metrics of a render actor, the
+/// lowest number not taken by another live render actor.
+///
+/// A label is taken over by a later connection once its render actor is
+/// gone, so that its series accumulate the connections made one after
+/// another.
+pub struct ConnectionLabel {
+    slot: Option<usize>,
+    value: String,
+}
+
+impl ConnectionLabel {
+    pub fn acquire() -> Self {
+        let mut slots = CONNECTION_SLOTS.lock().unwrap();
+        let slot = slots.iter().position(|taken| !taken);
+        if let Some(slot) = slot {
+            slots[slot] = true;
+        }
+        let value = slot.map_or_else(|| "other".to_owned(), |slot| slot.to_string());
+        Self { slot, value }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+}
+
+impl Drop for ConnectionLabel {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            CONNECTION_SLOTS.lock().unwrap()[slot] = false;
+        }
+    }
+}
+
+/// Events dropped because an actor lagged behind its broadcast channel, labeled by the actor.
+pub static LAGGED_EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "typst_preview_lagged_events_total",
+        "Number of events dropped because an actor lagged behind",
+        &["actor"]
+    )
+    .unwrap()
+});
+
+/// Deltas dropped because a webview fell behind, and replaced by a snapshot.
+pub static DROPPED_DELTAS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "typst_preview_dropped_deltas_total",
+        "Number of deltas dropped because a webview fell behind"
+    )
+    .unwrap()
+});
+
+/// Messages received from the editor, labeled by the event type.
+pub static CONTROL_PLANE_MESSAGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "typst_preview_control_plane_messages_total",
+        "Number of messages received from the editor",
+        &["event"]
+    )
+    .unwrap()
+});
+
+/// Encodes all metrics in the Prometheus text format.
+pub fn gather_metrics() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+/// The content type of [`gather_metrics`].
+pub const METRICS_CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
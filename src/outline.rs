@@ -2,7 +2,8 @@ use std::num::NonZeroUsize;
 
 use serde::Serialize;
 use typst::foundations::{Content, NativeElement, Packed, StyleChain};
-use typst::introspection::Introspector;
+use typst::introspection::{Introspector, Location};
+use typst::layout::Position;
 use typst::model::HeadingElem;
 use typst::syntax::Span;
 use typst_ts_core::debug_loc::DocumentPosition;
@@ -15,12 +16,23 @@ use crate::debug_loc::SpanInternerImpl;
 pub(crate) struct HeadingNode {
     body: Content,
     span: Span,
+    location: Location,
     position: DocumentPosition,
     level: NonZeroUsize,
     bookmarked: bool,
     children: Vec<HeadingNode>,
 }
 
+/// Converts a position in the document to the one sent to the webview and
+/// the editor, in points from the top left of the page.
+pub(crate) fn document_position(pos: Position) -> DocumentPosition {
+    DocumentPosition {
+        page_no: pos.page.into(),
+        x: pos.point.x.to_pt() as f32,
+        y: pos.point.y.to_pt() as f32,
+    }
+}
+
 /// Construct the outline for the document.
 pub(crate) fn get_outline(introspector: &Introspector) -> Option<Vec<HeadingNode>> {
     let mut tree: Vec<HeadingNode> = vec![];
@@ -98,15 +110,8 @@ pub(crate) fn get_outline(introspector: &Introspector) -> Option<Vec<HeadingNode
 
 impl HeadingNode {
     fn leaf(introspector: &Introspector, element: &Packed<HeadingElem>) -> Self {
-        let position = {
-            let loc = element.location().unwrap();
-            let pos = introspector.position(loc);
-            DocumentPosition {
-                page_no: pos.page.into(),
-                x: pos.point.x.to_pt() as f32,
-                y: pos.point.y.to_pt() as f32,
-            }
-        };
+        let location = element.location().unwrap();
+        let position = document_position(introspector.position(location));
 
         HeadingNode {
             level: element.resolve_level(StyleChain::default()),
@@ -117,9 +122,36 @@ impl HeadingNode {
                 .unwrap_or_else(|| element.outlined(StyleChain::default())),
             body: element.body.clone(),
             span: element.span(),
+            location,
             children: Vec::new(),
         }
     }
+
+    /// The plain text title of the heading.
+    pub(crate) fn title(&self) -> String {
+        self.body.plain_text().trim().to_owned()
+    }
+
+    /// The span of the heading, or of its body if the heading has none.
+    pub(crate) fn span(&self) -> Span {
+        if self.span.is_detached() {
+            self.body.span()
+        } else {
+            self.span
+        }
+    }
+
+    pub(crate) fn location(&self) -> Location {
+        self.location
+    }
+
+    pub(crate) fn position(&self) -> DocumentPosition {
+        self.position
+    }
+
+    pub(crate) fn children(&self) -> &[HeadingNode] {
+        &self.children
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
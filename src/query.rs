@@ -0,0 +1,151 @@
+use comemo::Track;
+use serde::Serialize;
+use typst::diag::{At, SourceDiagnostic, SourceResult};
+use typst::eval::{eval_string, EvalMode};
+use typst::foundations::{Content, LocatableSelector, Scope};
+use typst::layout::Position;
+use typst::syntax::Span;
+use typst::World;
+use typst_ts_core::debug_loc::DocumentPosition;
+use typst_ts_core::TypstDocument;
+
+use crate::debug_loc::SpanInternerImpl;
+use crate::diagnostics::{resolve_diagnostics, CompileDiagnostic};
+use crate::outline::document_position;
+
+/// An element of the document matched by a query.
+#[derive(Debug, Clone)]
+pub struct QueryMatch {
+    pub element: Content,
+    pub position: Option<Position>,
+}
+
+/// The elements matched by a query, or the diagnostics of a failed query.
+pub type QueryResult = Result<Vec<QueryMatch>, Vec<CompileDiagnostic>>;
+
+/// Runs a selector query against the document, e.g. `<label>`, `metadata` or
+/// `figure.where(kind: table)`, like `typst query` does.
+pub fn query(world: &dyn World, document: Option<&TypstDocument>, selector: &str) -> QueryResult {
+    let matches = || -> SourceResult<Vec<QueryMatch>> {
+        let Some(document) = document else {
+            let error =
+                SourceDiagnostic::error(Span::detached(), "the document is not compiled yet");
+            return Err(vec![error].into());
+        };
+        let selector = eval_string(
+            world.track(),
+            selector,
+            Span::detached(),
+            EvalMode::Code,
+            Scope::default(),
+        )?
+        .cast::<LocatableSelector>()
+        .at(Span::detached())?;
+
+        let introspector = &document.introspector;
+        Ok(introspector
+            .query(&selector.0)
+            .into_iter()
+            .map(|element| QueryMatch {
+                position: element.location().map(|loc| introspector.position(loc)),
+                element,
+            })
+            .collect())
+    };
+
+    matches().map_err(|errors| resolve_diagnostics(world, &errors))
+}
+
+/// The answer to a `query` request of the editor.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryResponse {
+    /// The id of the request, if the editor gave one.
+    id: Option<serde_json::Value>,
+    elements: Vec<QueryElement>,
+    /// The errors of the query, empty if it succeeded.
+    diagnostics: Vec<CompileDiagnostic>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct QueryElement {
+    /// The fields of the element, along with its `func`.
+    fields: Content,
+    /// Span id in hex-format.
+    span: Option<String>,
+    /// The resolved position in the document.
+    position: Option<DocumentPosition>,
+}
+
+pub fn query_response(
+    interner: &mut SpanInternerImpl,
+    id: Option<serde_json::Value>,
+    result: QueryResult,
+) -> QueryResponse {
+    let (matches, diagnostics) = match result {
+        Ok(matches) => (matches, Vec::new()),
+        Err(diagnostics) => (Vec::new(), diagnostics),
+    };
+
+    let elements = matches
+        .into_iter()
+        .map(|m| {
+            let span = m.element.span();
+            QueryElement {
+                span: (!span.is_detached()).then(|| interner.intern(span).to_hex()),
+                position: m.position.map(document_position),
+                fields: m.element,
+            }
+        })
+        .collect();
+
+    QueryResponse {
+        id,
+        elements,
+        diagnostics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::watch;
+    use typst::foundations::{Label, Selector};
+
+    use super::*;
+    use crate::debug_loc::{InternQuery, SpanInterner};
+    use crate::search::tests::{compile, next_outline, outline_actor, resend};
+
+    #[tokio::test]
+    async fn test_query_result_outlives_resent_documents() {
+        let document = Arc::new(compile("= Intro <intro>\nSome text."));
+        let (document_tx, document_rx) = watch::channel(None);
+        let span_interner = SpanInterner::new();
+        let mut editor_rx = outline_actor(document_rx, span_interner.clone());
+        document_tx.send_replace(Some(document.clone()));
+        next_outline(&mut editor_rx).await;
+
+        let selector = Selector::Label(Label::new("intro"));
+        let matches = document
+            .introspector
+            .query(&selector)
+            .into_iter()
+            .map(|element| QueryMatch {
+                element,
+                position: None,
+            })
+            .collect();
+        let response = span_interner
+            .with_writer(|interner| query_response(interner, None, Ok(matches)))
+            .await;
+        let span = response.elements[0].span.clone().unwrap();
+
+        resend(&document_tx, &document, &mut editor_rx).await;
+        document_tx.send_replace(Some(Arc::new(compile("Other text."))));
+        next_outline(&mut editor_rx).await;
+        assert!(matches!(
+            span_interner.span_by_str(&span).await,
+            InternQuery::Ok(Some(_))
+        ));
+    }
+}
@@ -0,0 +1,486 @@
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use await_tree::InstrumentAwait;
+use log::error;
+use serde::Serialize;
+use tokio::{sync::watch, task};
+use typst::layout::{Abs, Frame, FrameItem, Point, Position, Transform};
+use typst::model::Document;
+use typst::syntax::Span;
+use typst::text::TextItem;
+use typst_ts_core::debug_loc::DocumentPosition;
+use typst_ts_core::TypstDocument;
+
+use crate::debug_loc::{SpanInterner, SpanInternerImpl};
+use crate::outline::document_position;
+use crate::SearchRequest;
+
+/// The most hits of a search, the rest are left out.
+const MAX_HITS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    /// The id of the request, if it gave one.
+    id: Option<serde_json::Value>,
+    query: String,
+    hits: Vec<SearchHit>,
+    /// Whether there are more hits than [`MAX_HITS`].
+    truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SearchHit {
+    /// Span id in hex-format, of the first matched character.
+    span: Option<String>,
+    /// The areas of the matched text, one for each run of text it spans.
+    ranges: Vec<SearchRange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SearchRange {
+    /// The top left corner of the area.
+    start: DocumentPosition,
+    /// The bottom right corner of the area.
+    end: DocumentPosition,
+}
+
+/// A run of text on a page, with the transform of its baseline.
+pub(crate) struct Run<'a> {
+    pub ts: Transform,
+    pub item: &'a TextItem,
+}
+
+/// A character of the searched text of a page.
+#[derive(Clone, Copy)]
+struct PageChar {
+    /// The index of the run of the character, `None` for the space put
+    /// between two runs.
+    run: Option<usize>,
+    /// The byte offset of the character in the text of its run.
+    offset: usize,
+}
+
+/// The hits of a search, with the spans of their first characters to intern.
+#[derive(Default)]
+struct Hits {
+    hits: Vec<(Option<Span>, Vec<SearchRange>)>,
+    truncated: bool,
+}
+
+/// Searches the laid-out text of the document.
+///
+/// The text of the runs is joined in the order they are laid out, so that a
+/// query can span several lines. Whitespace matches any whitespace.
+fn search(document: Option<&TypstDocument>, req: &SearchRequest) -> Hits {
+    let mut needle = Vec::new();
+    for c in req.query.trim().chars() {
+        push_folded(&mut needle, c, req.case_sensitive);
+    }
+
+    let mut hits = Vec::new();
+    let mut truncated = false;
+    let pages = match document {
+        Some(document) if !needle.is_empty() => document.pages.as_slice(),
+        _ => &[],
+    };
+    'pages: for (i, page) in pages.iter().enumerate() {
+        let page_no = NonZeroUsize::new(i + 1).unwrap();
+        let mut runs = Vec::new();
+        collect_runs(&page.frame, Transform::identity(), &mut runs);
+
+        let mut haystack = Vec::new();
+        let mut chars = Vec::new();
+        let mut last_end = None;
+        for (r, run) in runs.iter().enumerate() {
+            let start = Point::zero().transform(run.ts);
+            // adjacent runs may split a word, e.g. when its style changes
+            let adjacent = last_end.is_some_and(|(y, x): (Abs, Abs)| {
+                y == start.y && (start.x - x).abs() < Abs::pt(0.5)
+            });
+            if !adjacent && push_folded(&mut haystack, ' ', true) > 0 {
+                chars.push(PageChar {
+                    run: None,
+                    offset: 0,
+                });
+            }
+            last_end = Some((start.y, Point::with_x(run.item.width()).transform(run.ts).x));
+
+            for (offset, c) in run.item.text.char_indices() {
+                let pushed = push_folded(&mut haystack, c, req.case_sensitive);
+                for _ in 0..pushed {
+                    chars.push(PageChar {
+                        run: Some(r),
+                        offset,
+                    });
+                }
+            }
+        }
+
+        let mut at = 0;
+        while at + needle.len() <= haystack.len() {
+            if haystack[at..at + needle.len()] != needle[..] {
+                at += 1;
+                continue;
+            }
+            if hits.len() == MAX_HITS {
+                truncated = true;
+                break 'pages;
+            }
+            hits.push(search_hit(page_no, &runs, &chars[at..at + needle.len()]));
+            at += needle.len();
+        }
+    }
+
+    Hits { hits, truncated }
+}
+
+/// Pushes the character to the searched text, lowercased unless the search
+/// is case sensitive, and with whitespace collapsed to a single space.
+///
+/// Returns the number of characters pushed.
+fn push_folded(text: &mut Vec<char>, c: char, case_sensitive: bool) -> usize {
+    if c.is_whitespace() {
+        if text.last().map_or(true, |last| *last == ' ') {
+            return 0;
+        }
+        text.push(' ');
+        return 1;
+    }
+    if case_sensitive {
+        text.push(c);
+        return 1;
+    }
+    let len = text.len();
+    text.extend(c.to_lowercase());
+    text.len() - len
+}
+
+/// Collects the runs of text of the frame, in the order they are laid out.
+pub(crate) fn collect_runs<'a>(frame: &'a Frame, ts: Transform, res: &mut Vec<Run<'a>>) {
+    for (pos, item) in frame.items() {
+        let ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+        match item {
+            FrameItem::Group(group) => {
+                collect_runs(&group.frame, ts.pre_concat(group.transform), res);
+            }
+            FrameItem::Text(item) => res.push(Run { ts, item }),
+            _ => {}
+        }
+    }
+}
+
+fn search_hit(
+    page: NonZeroUsize,
+    runs: &[Run],
+    chars: &[PageChar],
+) -> (Option<Span>, Vec<SearchRange>) {
+    let mut span = None;
+    let mut ranges = Vec::new();
+
+    // the matched characters of a run are contiguous
+    let mut i = 0;
+    while i < chars.len() {
+        let Some(r) = chars[i].run else {
+            i += 1;
+            continue;
+        };
+        let first = chars[i].offset;
+        let mut last = first;
+        while i < chars.len() && chars[i].run == Some(r) {
+            last = chars[i].offset;
+            i += 1;
+        }
+
+        let run = &runs[r];
+        let size = run.item.size;
+        let mut x = Abs::zero();
+        let mut extent: Option<(Abs, Abs)> = None;
+        for glyph in run.item.glyphs.iter() {
+            let advance = glyph.x_advance.at(size);
+            let range = glyph.range();
+            if range.start <= last && range.end > first {
+                extent = Some(match extent {
+                    Some((min, max)) => (min.min(x), max.max(x + advance)),
+                    None => (x, x + advance),
+                });
+                if span.is_none() && !glyph.span.0.is_detached() {
+                    span = Some(glyph.span.0);
+                }
+            }
+            x += advance;
+        }
+        let Some((min, max)) = extent else {
+            continue;
+        };
+
+        let metrics = run.item.font.metrics();
+        let a = Point::new(min, -metrics.ascender.at(size)).transform(run.ts);
+        let b = Point::new(max, -metrics.descender.at(size)).transform(run.ts);
+        let position = |x: Abs, y: Abs| {
+            document_position(Position {
+                page,
+                point: Point::new(x, y),
+            })
+        };
+        ranges.push(SearchRange {
+            start: position(a.x.min(b.x), a.y.min(b.y)),
+            end: position(a.x.max(b.x), a.y.max(b.y)),
+        });
+    }
+
+    (span, ranges)
+}
+
+fn intern_hits(
+    interner: &mut SpanInternerImpl,
+    hits: Vec<(Option<Span>, Vec<SearchRange>)>,
+) -> Vec<SearchHit> {
+    hits.into_iter()
+        .map(|(span, ranges)| SearchHit {
+            span: span.map(|span| interner.intern(span).to_hex()),
+            ranges,
+        })
+        .collect()
+}
+
+/// Searches the latest document for the webviews and the editor.
+#[derive(Clone)]
+pub struct DocumentSearch {
+    document: watch::Receiver<Option<Arc<Document>>>,
+    span_interner: SpanInterner,
+}
+
+impl DocumentSearch {
+    pub fn new(
+        document: watch::Receiver<Option<Arc<Document>>>,
+        span_interner: SpanInterner,
+    ) -> Self {
+        Self {
+            document,
+            span_interner,
+        }
+    }
+
+    /// Searches on a blocking thread, and takes the span interner only to
+    /// intern the spans of the hits.
+    pub async fn search(&self, req: &SearchRequest) -> SearchResult {
+        let document = self.document.borrow().clone();
+        let blocking_req = req.clone();
+        let found = task::spawn_blocking(move || search(document.as_deref(), &blocking_req))
+            .instrument_await("searching on a blocking thread")
+            .await
+            .unwrap_or_else(|err| {
+                error!("DocumentSearch: search failed: {}", err);
+                Hits::default()
+            });
+        let hits = self
+            .span_interner
+            .with_writer(|interner| intern_hits(interner, found.hits))
+            .instrument_await("interning spans of search hits")
+            .await;
+        SearchResult {
+            id: req.id.clone(),
+            query: req.query.clone(),
+            hits,
+            truncated: found.truncated,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Duration;
+
+    use comemo::Prehashed;
+    use tokio::sync::{broadcast, mpsc};
+    use typst::diag::{FileError, FileResult};
+    use typst::eval::Tracer;
+    use typst::foundations::{Bytes, Datetime};
+    use typst::syntax::{FileId, Source};
+    use typst::text::{Font, FontBook};
+    use typst::{Library, World};
+
+    use super::*;
+    use crate::actor::editor::EditorActorRequest;
+    use crate::actor::render::OutlineRenderActor;
+    use crate::debug_loc::InternQuery;
+
+    /// A world of a single source, with the embedded fonts.
+    struct TestWorld {
+        library: Prehashed<Library>,
+        book: Prehashed<FontBook>,
+        fonts: Vec<Font>,
+        main: Source,
+    }
+
+    impl World for TestWorld {
+        fn library(&self) -> &Prehashed<Library> {
+            &self.library
+        }
+
+        fn book(&self) -> &Prehashed<FontBook> {
+            &self.book
+        }
+
+        fn main(&self) -> Source {
+            self.main.clone()
+        }
+
+        fn source(&self, id: FileId) -> FileResult<Source> {
+            if id == self.main.id() {
+                Ok(self.main.clone())
+            } else {
+                Err(FileError::AccessDenied)
+            }
+        }
+
+        fn file(&self, _id: FileId) -> FileResult<Bytes> {
+            Err(FileError::AccessDenied)
+        }
+
+        fn font(&self, index: usize) -> Option<Font> {
+            self.fonts.get(index).cloned()
+        }
+
+        fn today(&self, _offset: Option<i64>) -> Option<Datetime> {
+            None
+        }
+    }
+
+    pub(crate) fn compile(text: &str) -> TypstDocument {
+        let fonts: Vec<_> = typst_assets::fonts()
+            .flat_map(|data| Font::iter(Bytes::from_static(data)))
+            .collect();
+        let world = TestWorld {
+            library: Prehashed::new(Library::default()),
+            book: Prehashed::new(FontBook::from_fonts(&fonts)),
+            fonts,
+            main: Source::detached(text),
+        };
+        typst::compile(&world, &mut Tracer::new()).unwrap()
+    }
+
+    fn request(query: &str, case_sensitive: bool) -> SearchRequest {
+        SearchRequest {
+            id: None,
+            query: query.to_owned(),
+            case_sensitive,
+        }
+    }
+
+    fn folded(text: &str, case_sensitive: bool) -> String {
+        let mut res = Vec::new();
+        for c in text.chars() {
+            push_folded(&mut res, c, case_sensitive);
+        }
+        res.into_iter().collect()
+    }
+
+    #[test]
+    fn test_push_folded() {
+        assert_eq!(folded("  Foo \t\n Bar ", false), "foo bar ");
+        assert_eq!(folded("Foo  Bar", true), "Foo Bar");
+        // lowercasing may take several characters
+        assert_eq!(folded("İ", false).chars().count(), 2);
+        let mut text = Vec::new();
+        assert_eq!(push_folded(&mut text, ' ', false), 0);
+        assert_eq!(push_folded(&mut text, 'İ', false), 2);
+        assert_eq!(push_folded(&mut text, ' ', false), 1);
+        assert_eq!(push_folded(&mut text, '\n', false), 0);
+    }
+
+    #[test]
+    fn test_search_across_runs() {
+        let document = compile("foo#text(fill: red)[bar] baz foobar");
+        let found = search(Some(&document), &request("FooBar", false));
+        assert_eq!(found.hits.len(), 2);
+        assert!(!found.truncated);
+        // the first hit spans both styled runs
+        assert_eq!(found.hits[0].1.len(), 2);
+        assert_eq!(found.hits[1].1.len(), 1);
+        assert!(found.hits.iter().all(|(span, _)| span.is_some()));
+
+        let found = search(Some(&document), &request("FooBar", true));
+        assert!(found.hits.is_empty());
+    }
+
+    #[test]
+    fn test_search_across_lines() {
+        let document = compile("alpha \\\n  beta\n\n#pagebreak()\nalpha beta");
+        let found = search(Some(&document), &request("alpha   beta", false));
+        assert_eq!(found.hits.len(), 2);
+        let (_, ranges) = &found.hits[0];
+        assert_eq!(ranges.len(), 2);
+        assert!(ranges[0].start.y < ranges[1].start.y);
+        assert_eq!(found.hits[1].1[0].start.page_no, 2);
+    }
+
+    #[test]
+    fn test_search_empty_query() {
+        let document = compile("alpha");
+        assert!(search(Some(&document), &request("  ", false))
+            .hits
+            .is_empty());
+        assert!(search(None, &request("alpha", false)).hits.is_empty());
+    }
+
+    /// Runs an outline actor on the documents of the returned sender, along
+    /// with the editor mailbox it reports to.
+    pub(crate) fn outline_actor(
+        document: watch::Receiver<Option<Arc<Document>>>,
+        span_interner: SpanInterner,
+    ) -> mpsc::UnboundedReceiver<EditorActorRequest> {
+        let (editor_tx, editor_rx) = mpsc::unbounded_channel();
+        let (webview_tx, _) = broadcast::channel(16);
+        OutlineRenderActor::new(document, editor_tx, webview_tx, span_interner).spawn();
+        editor_rx
+    }
+
+    /// Waits for the outline of the next document the outline actor reports.
+    pub(crate) async fn next_outline(editor_rx: &mut mpsc::UnboundedReceiver<EditorActorRequest>) {
+        while let Some(msg) = editor_rx.recv().await {
+            if let EditorActorRequest::Outline(_) = msg {
+                return;
+            }
+        }
+        panic!("the outline actor stopped");
+    }
+
+    /// Sends the document again more times than spans are kept for, like the
+    /// render requests of the webviews once did, and checks that the outline
+    /// actor ignores it.
+    pub(crate) async fn resend(
+        document_tx: &watch::Sender<Option<Arc<Document>>>,
+        document: &Arc<Document>,
+        editor_rx: &mut mpsc::UnboundedReceiver<EditorActorRequest>,
+    ) {
+        for _ in 0..32 {
+            document_tx.send_replace(Some(document.clone()));
+            let outline = tokio::time::timeout(Duration::from_millis(20), next_outline(editor_rx));
+            assert!(outline.await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hits_outlive_resent_documents() {
+        let document = Arc::new(compile("= Intro\nSome text."));
+        let (document_tx, document_rx) = watch::channel(None);
+        let span_interner = SpanInterner::new();
+        let mut editor_rx = outline_actor(document_rx.clone(), span_interner.clone());
+        document_tx.send_replace(Some(document.clone()));
+        next_outline(&mut editor_rx).await;
+
+        let search = DocumentSearch::new(document_rx, span_interner.clone());
+        let result = search.search(&request("text", false)).await;
+        let span = result.hits[0].span.clone().unwrap();
+
+        resend(&document_tx, &document, &mut editor_rx).await;
+        document_tx.send_replace(Some(Arc::new(compile("Other text."))));
+        next_outline(&mut editor_rx).await;
+        assert!(matches!(
+            span_interner.span_by_str(&span).await,
+            InternQuery::Ok(Some(_))
+        ));
+    }
+}
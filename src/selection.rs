@@ -0,0 +1,254 @@
+use serde::Serialize;
+use typst::layout::{Abs, FrameItem, Point, Transform};
+use typst::text::TextItem;
+use typst_ts_core::debug_loc::{ElementPoint, SourceSpanOffset};
+use typst_ts_core::TypstDocument;
+
+use crate::search::{collect_runs, Run};
+use crate::DocToSrcJumpInfo;
+
+/// The spans of the first and last glyphs of a selection, along with its
+/// text.
+#[derive(Debug)]
+pub struct SelectionSpans {
+    pub webview: usize,
+    pub text: String,
+    pub start: (SourceSpanOffset, SourceSpanOffset),
+    pub end: (SourceSpanOffset, SourceSpanOffset),
+}
+
+/// The text selected in a webview, and the source it comes from.
+#[derive(Debug, Clone, Serialize)]
+pub struct TextSelection {
+    /// The id of the webview which made the selection.
+    #[serde(skip)]
+    pub webview: usize,
+    /// The plain text of the selection, in the order it is laid out.
+    pub text: String,
+    /// The source range of the selection, if it could be resolved.
+    pub source: Option<SourceRange>,
+}
+
+/// A range in a source file, like [`DocToSrcJumpInfo`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceRange {
+    pub filepath: String,
+    /// Row and column of the start.
+    pub start: Option<(usize, usize)>,
+    /// Row and column of the end.
+    pub end: Option<(usize, usize)>,
+}
+
+impl From<DocToSrcJumpInfo> for SourceRange {
+    fn from(info: DocToSrcJumpInfo) -> Self {
+        Self {
+            filepath: info.filepath,
+            start: info.start,
+            end: info.end,
+        }
+    }
+}
+
+/// The kinds of the points of element paths, see the `span2vec` pass of
+/// `typst_ts_core`.
+pub(crate) const ELEMENT_TEXT: u32 = 0;
+pub(crate) const ELEMENT_GROUP: u32 = 1;
+pub(crate) const ELEMENT_PAGE: u32 = 4;
+pub(crate) const ELEMENT_CHAR_INDEX: u32 = 5;
+
+/// Extracts the laid-out text between the glyphs the two element paths point
+/// to, both included.
+///
+/// Runs on different lines are joined by a line break, and runs apart on the
+/// same line by a space.
+pub fn selection_text(
+    document: &TypstDocument,
+    start: &[ElementPoint],
+    end: &[ElementPoint],
+) -> Option<String> {
+    let (start_page, start_item, start_glyph) = text_at(document, start)?;
+    let (end_page, end_item, end_glyph) = text_at(document, end)?;
+
+    let mut runs = Vec::new();
+    for i in start_page.min(end_page)..=start_page.max(end_page) {
+        let mut page_runs = Vec::new();
+        collect_runs(
+            &document.pages[i].frame,
+            Transform::identity(),
+            &mut page_runs,
+        );
+        runs.extend(page_runs.into_iter().map(|run| (i, run)));
+    }
+
+    let find = |item: &TextItem, glyph: Option<usize>| {
+        let r = runs
+            .iter()
+            .position(|(_, run)| std::ptr::eq(run.item, item))?;
+        Some((r, glyph))
+    };
+    let mut from = find(start_item, start_glyph)?;
+    let mut to = find(end_item, end_glyph)?;
+    // the selection may be made backwards
+    if to < from {
+        std::mem::swap(&mut from, &mut to);
+    }
+    // a path to a whole run selects all of it
+    let from = (from.0, from.1.unwrap_or(0));
+    let to = (to.0, to.1.unwrap_or(usize::MAX));
+
+    let mut text = String::new();
+    let mut last: Option<(usize, Point)> = None;
+    for (r, (page, run)) in runs.iter().enumerate().take(to.0 + 1).skip(from.0) {
+        let item = run.item;
+        let first = if r == from.0 { from.1 } else { 0 };
+        let last_glyph = if r == to.0 {
+            to.1.min(item.glyphs.len().saturating_sub(1))
+        } else {
+            item.glyphs.len().saturating_sub(1)
+        };
+        let Some(glyphs) = item.glyphs.get(first..=last_glyph) else {
+            continue;
+        };
+        // the glyphs of right-to-left text are in reverse order
+        let lo = glyphs.iter().map(|g| g.range().start).min().unwrap_or(0);
+        let hi = glyphs.iter().map(|g| g.range().end).max().unwrap_or(0);
+
+        let start = Point::zero().transform(run.ts);
+        if let Some((last_page, last_end)) = last {
+            if last_page != *page || last_end.y != start.y {
+                text.push('\n');
+            } else if (start.x - last_end.x).abs() >= Abs::pt(0.5) {
+                text.push(' ');
+            }
+        }
+        last = Some((*page, end_of(run)));
+        text.push_str(&item.text[lo..hi]);
+    }
+
+    Some(text)
+}
+
+/// Follows an element path down the frames of the document to the run of
+/// text it points to, along with the page of the run and the index of the
+/// glyph, if the path points to one.
+///
+/// The page is followed by a group for the frame of the page. Then at each
+/// level, the path indexes the items the renderer keeps the spans of, which
+/// are the groups, text, shapes and images.
+pub(crate) fn text_at<'a>(
+    document: &'a TypstDocument,
+    path: &[ElementPoint],
+) -> Option<(usize, &'a TextItem, Option<usize>)> {
+    let (page, path) = path.split_first()?;
+    if page.kind != ELEMENT_PAGE {
+        return None;
+    }
+    let (_, path) = path
+        .split_first()
+        .filter(|(frame, _)| frame.kind == ELEMENT_GROUP)?;
+    let page_no = page.index as usize;
+    let mut frame = &document.pages.get(page_no)?.frame;
+    let mut text = None;
+    let mut glyph = None;
+    for point in path {
+        if let Some(text) = text {
+            if point.kind == ELEMENT_CHAR_INDEX {
+                glyph = Some(point.index as usize);
+            }
+            return Some((page_no, text, glyph));
+        }
+        let item = frame
+            .items()
+            .map(|(_, item)| item)
+            .filter(|item| {
+                matches!(
+                    item,
+                    FrameItem::Group(_)
+                        | FrameItem::Text(_)
+                        | FrameItem::Shape(..)
+                        | FrameItem::Image(..)
+                )
+            })
+            .nth(point.index as usize)?;
+        match (point.kind, item) {
+            (ELEMENT_GROUP, FrameItem::Group(group)) => frame = &group.frame,
+            (ELEMENT_TEXT, FrameItem::Text(item)) => text = Some(item),
+            _ => return None,
+        }
+    }
+    Some((page_no, text?, glyph))
+}
+
+fn end_of(run: &Run) -> Point {
+    Point::with_x(run.item.width()).transform(run.ts)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use typst::layout::Frame;
+
+    use super::*;
+    use crate::search::tests::compile;
+
+    /// Collects the paths to all runs of text in a frame, in layout order.
+    pub(crate) fn text_paths(
+        frame: &Frame,
+        path: &mut Vec<ElementPoint>,
+        out: &mut Vec<Vec<ElementPoint>>,
+    ) {
+        let items = frame.items().map(|(_, item)| item).filter(|item| {
+            matches!(
+                item,
+                FrameItem::Group(_)
+                    | FrameItem::Text(_)
+                    | FrameItem::Shape(..)
+                    | FrameItem::Image(..)
+            )
+        });
+        for (i, item) in items.enumerate() {
+            let kind = match item {
+                FrameItem::Group(_) => ELEMENT_GROUP,
+                FrameItem::Text(_) => ELEMENT_TEXT,
+                _ => continue,
+            };
+            path.push(point(kind, i));
+            match item {
+                FrameItem::Group(group) => text_paths(&group.frame, path, out),
+                _ => out.push(path.clone()),
+            }
+            path.pop();
+        }
+    }
+
+    pub(crate) fn point(kind: u32, index: usize) -> ElementPoint {
+        ElementPoint {
+            kind,
+            index: index as u32,
+            fingerprint: String::new(),
+        }
+    }
+
+    fn glyph(path: &[ElementPoint], index: usize) -> Vec<ElementPoint> {
+        let mut path = path.to_vec();
+        path.push(point(ELEMENT_CHAR_INDEX, index));
+        path
+    }
+
+    #[test]
+    fn test_selection_of_repeated_text() {
+        let document = compile("Same words.\n\nSame words.\n\nOther.");
+        let mut paths = Vec::new();
+        text_paths(
+            &document.pages[0].frame,
+            &mut vec![point(ELEMENT_PAGE, 0), point(ELEMENT_GROUP, 0)],
+            &mut paths,
+        );
+        assert_eq!(paths.len(), 3);
+
+        let text = selection_text(&document, &glyph(&paths[1], 0), &glyph(&paths[2], 2));
+        assert_eq!(text.as_deref(), Some("Same words.\nOth"));
+        // backwards, and from a whole run
+        let text = selection_text(&document, &paths[2], &glyph(&paths[1], 5));
+        assert_eq!(text.as_deref(), Some("words.\nOther."));
+    }
+}
@@ -0,0 +1,63 @@
+use serde::Serialize;
+use typst::foundations::{Label, Selector, Value};
+use typst::introspection::MetadataElem;
+use typst_ts_core::TypstDocument;
+
+/// The label attached to the metadata emitted by `pdfpc`-compatible slide
+/// packages, e.g. `#metadata((t: "Note", v: "..."))<pdfpc>` by polylux and
+/// touying.
+const PDFPC_LABEL: &str = "pdfpc";
+
+/// Speaker notes of a slide document.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakerNotes {
+    /// The number of pages in the document.
+    pages: usize,
+    /// The notes, in document order.
+    notes: Vec<SpeakerNote>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SpeakerNote {
+    /// The page the note belongs to, starting at 1.
+    page_no: usize,
+    /// Plain text of the note.
+    note: String,
+}
+
+/// Extracts the speaker notes from the document.
+pub fn speaker_notes(document: &TypstDocument) -> SpeakerNotes {
+    let introspector = &document.introspector;
+    let elements = introspector.query(&Selector::Label(Label::new(PDFPC_LABEL)));
+
+    let mut notes = vec![];
+    for elem in elements.iter() {
+        let Some(metadata) = elem.to_packed::<MetadataElem>() else {
+            continue;
+        };
+        let Value::Dict(dict) = &metadata.value else {
+            continue;
+        };
+        if !matches!(dict.get("t"), Ok(Value::Str(t)) if t.as_str() == "Note") {
+            continue;
+        }
+        let note = match dict.get("v") {
+            Ok(Value::Str(s)) => s.as_str().to_owned(),
+            Ok(Value::Content(c)) => c.plain_text().to_string(),
+            _ => continue,
+        };
+        let Some(loc) = metadata.location() else {
+            continue;
+        };
+
+        notes.push(SpeakerNote {
+            page_no: introspector.page(loc).into(),
+            note: note.trim().to_owned(),
+        });
+    }
+
+    SpeakerNotes {
+        pages: document.pages.len(),
+        notes,
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use serde::Serialize;
+use typst::foundations::{Content, NativeElement};
+use typst::introspection::{Location, Meta};
+use typst::layout::{Abs, Frame, FrameItem, Point, Size, Transform};
+use typst::model::{BibliographyElem, Destination, FigureElem, FootnoteElem};
+use typst::syntax::Span;
+use typst_ts_core::debug_loc::DocumentPosition;
+use typst_ts_core::TypstDocument;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::debug_loc::SpanInternerImpl;
+use crate::outline::{get_outline, HeadingNode};
+use crate::StatsExclusion;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentStats {
+    pages: usize,
+    words: usize,
+    /// The number of characters, not counting whitespace.
+    characters: usize,
+    /// The counts of the sections, in the tree of the outline.
+    sections: Vec<SectionStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SectionStats {
+    /// Plain text title.
+    title: String,
+    /// Span id in hex-format.
+    span: Option<String>,
+    /// The resolved position in the document.
+    position: DocumentPosition,
+    /// The number of words of the section, including its subsections.
+    words: usize,
+    /// The number of characters of the section, including its subsections.
+    characters: usize,
+    children: Vec<SectionStats>,
+}
+
+/// A run of text, laid out on a baseline.
+struct Run {
+    start: Point,
+    end: Point,
+    text: String,
+    /// The span of the first glyph of the run, and its offset in the span.
+    span: (Span, u16),
+    /// The section in which the run is laid out, if it follows a heading.
+    section: Option<usize>,
+}
+
+/// The text of a page, laid out in lines.
+#[derive(Default)]
+struct PageText {
+    /// The runs of text, in layout order.
+    runs: Vec<Run>,
+    /// The areas of the content left out of the counts.
+    excluded: Vec<(Point, Point)>,
+    /// The tops of the footnote entries, and the locations of their footnotes.
+    footnotes: Vec<(Abs, Location)>,
+}
+
+/// The state of the walk through the frames of the document, in layout order.
+struct Walk<'a> {
+    exclude: &'a [StatsExclusion],
+    /// The indices of the sections by the locations of their headings.
+    headings: HashMap<Location, usize>,
+    /// The section of the last heading laid out.
+    section: Option<usize>,
+    /// The sections in which the footnotes are laid out.
+    footnotes: HashMap<Location, Option<usize>>,
+}
+
+/// Counts the words and characters of the laid-out text of the document, by
+/// the sections of its outline.
+///
+/// The text belongs to the section of the last heading laid out before it,
+/// in the order of layout, so that the columns of a page are read one after
+/// another. The running headers, footers and page numbers are left out, see
+/// [`bodies`].
+pub fn document_stats(
+    interner: &mut SpanInternerImpl,
+    document: &TypstDocument,
+    exclude: &[StatsExclusion],
+) -> DocumentStats {
+    let introspector = &document.introspector;
+    let outline = get_outline(introspector).unwrap_or_default();
+    // the sections in document order, each one collecting its own text
+    let mut headings = Vec::new();
+    flatten(&outline, &mut headings);
+    let mut texts = vec![String::new(); headings.len()];
+    let mut preamble = String::new();
+
+    let mut walk = Walk {
+        exclude,
+        headings: headings
+            .iter()
+            .enumerate()
+            .map(|(i, heading)| (heading.location(), i))
+            .collect(),
+        section: None,
+        footnotes: introspector
+            .query(&FootnoteElem::elem().select())
+            .iter()
+            .filter_map(Content::location)
+            .map(|loc| (loc, None))
+            .collect(),
+    };
+    let pages: Vec<_> = document
+        .pages
+        .iter()
+        .map(|page| {
+            let mut page_text = PageText::default();
+            collect_text(
+                &page.frame,
+                Transform::identity(),
+                &mut walk,
+                &mut page_text,
+            );
+            page_text.footnotes.sort_by(|a, b| a.0.cmp(&b.0));
+            page_text
+        })
+        .collect();
+    let exclude_footnotes = exclude.contains(&StatsExclusion::Footnotes);
+
+    let bodies = bodies(&pages);
+
+    for (page, body) in pages.into_iter().zip(bodies) {
+        let mut last_end = None;
+        for (i, run) in page.runs.into_iter().enumerate() {
+            if !body.contains(&i) || page.excluded.iter().any(|area| overlaps(area, &run)) {
+                continue;
+            }
+            // the text of a footnote entry belongs to the section of its
+            // footnote
+            let entry = page
+                .footnotes
+                .iter()
+                .rev()
+                .find(|(top, _)| *top <= run.start.y);
+            let section = match entry {
+                Some(_) if exclude_footnotes => continue,
+                Some((_, loc)) => walk.footnotes.get(loc).copied().unwrap_or(run.section),
+                None => run.section,
+            };
+
+            let text = match section {
+                None => &mut preamble,
+                Some(i) => &mut texts[i],
+            };
+            // adjacent runs may split a word, e.g. when its style changes
+            let adjacent = last_end.is_some_and(|end: Point| {
+                end.y == run.start.y && (run.start.x - end.x).abs() < Abs::pt(0.5)
+            });
+            if !adjacent {
+                text.push(' ');
+            }
+            last_end = Some(run.end);
+            text.push_str(&run.text);
+        }
+    }
+
+    let counts: Vec<_> = texts.iter().map(|text| count(text)).collect();
+    let mut next = 0;
+    let sections = section_stats(interner, &outline, &counts, &mut next);
+    let (words, characters) = counts
+        .iter()
+        .fold(count(&preamble), |(w, c), (sw, sc)| (w + sw, c + sc));
+
+    DocumentStats {
+        pages: document.pages.len(),
+        words,
+        characters,
+        sections,
+    }
+}
+
+/// Finds the runs of the body of each page, leaving out the running headers,
+/// footers and page numbers.
+///
+/// Typst puts the header of a page before the items of its body, and the
+/// footer after them. As it inlines their frames and does not keep the
+/// margins, they are told apart from the body by being laid out on other
+/// pages too, from the same glyphs of the source at the same height. So the
+/// body of a page ranges from its first to its last run not repeated like
+/// this. The text repeated by the body itself at the very top or bottom of
+/// pages, e.g. by a loop, is taken for marginals as well.
+fn bodies(pages: &[PageText]) -> Vec<Range<usize>> {
+    let origin = |run: &Run| (run.span, run.start.y);
+    let mut origin_pages = HashMap::<_, usize>::new();
+    for page in pages {
+        let origins: HashSet<_> = page.runs.iter().map(origin).collect();
+        for origin in origins {
+            *origin_pages.entry(origin).or_default() += 1;
+        }
+    }
+    let repeated = |run: &&Run| origin_pages[&origin(run)] > 1;
+
+    pages
+        .iter()
+        .map(|page| {
+            let head = page.runs.iter().take_while(repeated).count();
+            let foot = page.runs[head..].iter().rev().take_while(repeated).count();
+            head..page.runs.len() - foot
+        })
+        .collect()
+}
+
+fn flatten<'a>(nodes: &'a [HeadingNode], res: &mut Vec<&'a HeadingNode>) {
+    for node in nodes {
+        res.push(node);
+        flatten(node.children(), res);
+    }
+}
+
+/// Builds the stats of the sections from the counts of their own text, in
+/// document order.
+fn section_stats(
+    interner: &mut SpanInternerImpl,
+    nodes: &[HeadingNode],
+    counts: &[(usize, usize)],
+    next: &mut usize,
+) -> Vec<SectionStats> {
+    let mut res = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let (mut words, mut characters) = counts[*next];
+        *next += 1;
+        let children = section_stats(interner, node.children(), counts, next);
+        for child in children.iter() {
+            words += child.words;
+            characters += child.characters;
+        }
+
+        let span = node.span();
+        res.push(SectionStats {
+            title: node.title(),
+            span: (!span.is_detached()).then(|| interner.intern(span).to_hex()),
+            position: node.position(),
+            words,
+            characters,
+            children,
+        });
+    }
+    res
+}
+
+fn count(text: &str) -> (usize, usize) {
+    let words = text.unicode_words().count();
+    let characters = text.chars().filter(|c| !c.is_whitespace()).count();
+    (words, characters)
+}
+
+fn collect_text(frame: &Frame, ts: Transform, walk: &mut Walk, res: &mut PageText) {
+    for (pos, item) in frame.items() {
+        let ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+        match item {
+            FrameItem::Group(group) => {
+                collect_text(&group.frame, ts.pre_concat(group.transform), walk, res);
+            }
+            FrameItem::Text(text) => {
+                res.runs.push(Run {
+                    start: Point::zero().transform(ts),
+                    end: Point::with_x(text.width()).transform(ts),
+                    text: text.text.to_string(),
+                    span: text
+                        .glyphs
+                        .first()
+                        .map_or((Span::detached(), 0), |g| g.span),
+                    section: walk.section,
+                });
+            }
+            FrameItem::Meta(Meta::Elem(elem), size) => {
+                if let Some(loc) = elem.location() {
+                    if let Some(&section) = walk.headings.get(&loc) {
+                        walk.section = Some(section);
+                    } else if let Some(footnote) = walk.footnotes.get_mut(&loc) {
+                        *footnote = walk.section;
+                    }
+                }
+                if is_excluded(elem, walk.exclude) {
+                    res.excluded.push(area(ts, *size));
+                }
+            }
+            // the numbers of footnote entries link to their footnotes
+            FrameItem::Meta(Meta::Link(Destination::Location(loc)), _)
+                if walk.footnotes.contains_key(loc) =>
+            {
+                res.footnotes.push((Point::zero().transform(ts).y, *loc));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_excluded(elem: &Content, exclude: &[StatsExclusion]) -> bool {
+    (elem.is::<FigureElem>() && exclude.contains(&StatsExclusion::Figures))
+        || (elem.is::<BibliographyElem>() && exclude.contains(&StatsExclusion::Bibliography))
+}
+
+/// Whether the baseline of the run overlaps the area.
+fn overlaps((min, max): &(Point, Point), run: &Run) -> bool {
+    let (left, right) = extent(run.start.x, run.end.x);
+    let (top, bottom) = extent(run.start.y, run.end.y);
+    left <= max.x && min.x <= right && top <= max.y && min.y <= bottom
+}
+
+fn extent(a: Abs, b: Abs) -> (Abs, Abs) {
+    (a.min(b), a.max(b))
+}
+
+/// The bounding box of a transformed area.
+fn area(ts: Transform, size: Size) -> (Point, Point) {
+    let a = Point::zero().transform(ts);
+    let b = size.to_point().transform(ts);
+    (
+        Point::new(a.x.min(b.x), a.y.min(b.y)),
+        Point::new(a.x.max(b.x), a.y.max(b.y)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::tests::compile;
+
+    fn stats(text: &str, exclude: &[StatsExclusion]) -> DocumentStats {
+        let document = compile(text);
+        document_stats(&mut SpanInternerImpl::new(), &document, exclude)
+    }
+
+    /// A paragraph of the words, long enough to break across pages.
+    fn words(n: usize) -> String {
+        (0..n)
+            .map(|i| format!("w{i}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_sections() {
+        let stats = stats("Intro.\n= One\nA b c.\n== Two\nD e.\n= Three\nF.", &[]);
+        assert_eq!(stats.words, 1 + 1 + 3 + 1 + 2 + 1 + 1);
+        let sections: Vec<_> = stats
+            .sections
+            .iter()
+            .map(|section| (section.title.as_str(), section.words))
+            .collect();
+        assert_eq!(sections, [("One", 1 + 3 + 1 + 2), ("Three", 1 + 1)]);
+        assert_eq!(stats.sections[0].children[0].words, 1 + 2);
+    }
+
+    #[test]
+    fn test_marginals_are_left_out() {
+        let text = format!(
+            "#set page(width: 150pt, height: 150pt, header: [Running head], numbering: \"1\")\n{}",
+            words(100)
+        );
+        let stats = stats(&text, &[]);
+        assert!(stats.pages > 2);
+        assert_eq!(stats.words, 100);
+    }
+
+    #[test]
+    fn test_text_across_pages_is_counted() {
+        // the paragraph continues at the top of the pages, from the same span
+        let text = format!(
+            "#set page(width: 150pt, height: 150pt, header: [Running head])\n{}\n\n{}\n\nLast words.",
+            words(100),
+            words(100)
+        );
+        let stats = stats(&text, &[]);
+        assert!(stats.pages > 2);
+        assert_eq!(stats.words, 202);
+    }
+
+    #[test]
+    fn test_excluded_footnotes() {
+        let text = "Some text#footnote[A note of five words.] here.";
+        // along with the numbers of the footnote and its entry
+        assert_eq!(stats(text, &[]).words, 3 + 1 + 1 + 5);
+        assert_eq!(stats(text, &[StatsExclusion::Footnotes]).words, 3 + 1);
+    }
+}
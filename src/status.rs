@@ -0,0 +1,117 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::await_tree::Stall;
+use crate::{CompileInfo, CompileStatus};
+
+/// A snapshot of the status of the previewer.
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewStatus {
+    /// The status of the latest compilation.
+    pub compile_status: Option<CompileStatus>,
+    /// The duration of the latest finished compilation in milliseconds.
+    pub last_compile_duration_ms: Option<f64>,
+    /// The number of pages of the latest compiled document.
+    pub page_count: Option<usize>,
+    /// The number of connected webviews.
+    pub webviews: usize,
+    /// Whether an editor is attached to the control plane.
+    pub editor_attached: bool,
+    /// The actors stuck in one await point, see `--stall-threshold`.
+    pub stalls: Vec<Stall>,
+}
+
+#[derive(Default)]
+struct StatusState {
+    compile_status: Option<CompileStatus>,
+    /// The latest compiled document that no delta has been sent for yet.
+    awaiting_first_delta: Option<(Instant, CompileInfo)>,
+    last_compile_duration: Option<Duration>,
+    page_count: Option<usize>,
+    webviews: usize,
+    editor_attached: bool,
+    stalls: Vec<Stall>,
+}
+
+/// Tracks the status of the previewer.
+///
+/// Cloning the tracker is cheap, and the clones share the same status.
+#[derive(Clone, Default)]
+pub struct StatusTracker {
+    inner: Arc<Mutex<StatusState>>,
+}
+
+impl StatusTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes a snapshot of the current status.
+    pub fn snapshot(&self) -> PreviewStatus {
+        let state = self.inner.lock().unwrap();
+        PreviewStatus {
+            compile_status: state.compile_status.clone(),
+            last_compile_duration_ms: state.last_compile_duration.map(|d| d.as_secs_f64() * 1000.),
+            page_count: state.page_count,
+            webviews: state.webviews,
+            editor_attached: state.editor_attached,
+            stalls: state.stalls.clone(),
+        }
+    }
+
+    /// Whether a document has been compiled successfully.
+    pub fn is_ready(&self) -> bool {
+        self.inner.lock().unwrap().page_count.is_some()
+    }
+
+    pub(crate) fn compile_status(&self, status: CompileStatus) {
+        self.inner.lock().unwrap().compile_status = Some(status);
+    }
+
+    pub(crate) fn compiled(&self, compile_time: Duration) {
+        self.inner.lock().unwrap().last_compile_duration = Some(compile_time);
+    }
+
+    pub(crate) fn compiled_document(&self, info: CompileInfo) {
+        let mut state = self.inner.lock().unwrap();
+        state.page_count = Some(info.page_count);
+        // a webview connecting later would measure its own connection instead
+        if state.webviews > 0 {
+            state.awaiting_first_delta = Some((Instant::now(), info));
+        }
+    }
+
+    /// Records that a delta of the latest compiled document is sent to a
+    /// webview.
+    ///
+    /// Returns the statistics of the compilation on the first delta.
+    pub(crate) fn delta_sent(&self) -> Option<CompileInfo> {
+        let mut state = self.inner.lock().unwrap();
+        let (compiled_at, mut info) = state.awaiting_first_delta.take()?;
+        let since_compiled = compiled_at.elapsed().as_secs_f64() * 1000.;
+        info.first_delta_ms = Some(info.compile_ms + since_compiled);
+        if let Some(CompileStatus::CompileSuccess(_)) = state.compile_status {
+            state.compile_status = Some(CompileStatus::CompileSuccess(info.clone()));
+        }
+        Some(info)
+    }
+
+    pub(crate) fn webview_connected(&self) {
+        self.inner.lock().unwrap().webviews += 1;
+    }
+
+    pub(crate) fn webview_disconnected(&self) {
+        let mut state = self.inner.lock().unwrap();
+        state.webviews = state.webviews.saturating_sub(1);
+    }
+
+    pub(crate) fn set_editor_attached(&self, attached: bool) {
+        self.inner.lock().unwrap().editor_attached = attached;
+    }
+
+    pub(crate) fn set_stalls(&self, stalls: Vec<Stall>) {
+        self.inner.lock().unwrap().stalls = stalls;
+    }
+}
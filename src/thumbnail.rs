@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use hyper::{header, Body, Response, StatusCode};
+use log::error;
+use typst::layout::{Frame, Size};
+use typst::model::Document;
+use typst::visualize::Color;
+
+/// The resolution of PNG pages if not specified, the same as `typst compile`.
+const DEFAULT_PPI: f32 = 144.0;
+/// Caps the resolution of the requests.
+const MAX_PPI: f32 = 600.0;
+/// The most pixels of a rendered page, so that a large page cannot allocate a
+/// huge pixmap. An A4 page at [`MAX_PPI`] fits in it.
+const MAX_PIXELS: f32 = 50_000_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageFormat {
+    Png { ppi: f32 },
+    Svg,
+}
+
+/// Parses a page request like `/page/3.png?ppi=72` or `/page/3.svg`.
+///
+/// The page number starts at 1.
+pub fn parse_page_request(path: &str, query: Option<&str>) -> Option<(usize, PageFormat)> {
+    let file = path.strip_prefix("/page/")?;
+    let (page_no, ext) = file.rsplit_once('.')?;
+    let page_no = page_no.parse().ok().filter(|&n: &usize| n > 0)?;
+
+    let format = match ext {
+        "png" => {
+            let ppi = query
+                .into_iter()
+                .flat_map(|q| q.split('&'))
+                .filter_map(|kv| kv.split_once('='))
+                .find(|(k, _)| *k == "ppi")
+                .and_then(|(_, v)| v.parse::<f32>().ok())
+                .unwrap_or(DEFAULT_PPI);
+            if ppi.is_nan() || ppi <= 0. {
+                return None;
+            }
+            PageFormat::Png {
+                ppi: ppi.min(MAX_PPI),
+            }
+        }
+        "svg" => PageFormat::Svg,
+        _ => return None,
+    };
+
+    Some((page_no, format))
+}
+
+/// Renders a page of the latest compiled document.
+pub async fn serve_page(
+    document: Option<Arc<Document>>,
+    page_no: usize,
+    format: PageFormat,
+) -> Response<Body> {
+    let Some(document) = document else {
+        return status_response(StatusCode::SERVICE_UNAVAILABLE, "document is not ready");
+    };
+    if page_no > document.pages.len() {
+        return status_response(StatusCode::NOT_FOUND, "page not found");
+    }
+
+    let rendered = tokio::task::spawn_blocking(move || {
+        let frame = &document.pages[page_no - 1].frame;
+        match format {
+            PageFormat::Png { ppi } => render_png(frame, ppi).map(|png| ("image/png", png)),
+            PageFormat::Svg => Ok(("image/svg+xml", typst_svg::svg(frame).into_bytes())),
+        }
+    })
+    .await;
+
+    match rendered {
+        Ok(Ok((content_type, data))) => {
+            let mut res = Response::new(Body::from(data));
+            let headers = res.headers_mut();
+            headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+            headers.insert(header::CACHE_CONTROL, "no-cache".parse().unwrap());
+            res
+        }
+        Ok(Err(err)) => {
+            error!("failed to encode page {page_no}: {err}");
+            status_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to render page")
+        }
+        Err(err) => {
+            error!("failed to render page {page_no}: {err}");
+            status_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to render page")
+        }
+    }
+}
+
+fn render_png(frame: &Frame, ppi: f32) -> Result<Vec<u8>, String> {
+    let pixel_per_pt = pixel_per_pt(frame.size(), ppi)?;
+    typst_render::render(frame, pixel_per_pt, Color::WHITE)
+        .encode_png()
+        .map_err(|err| err.to_string())
+}
+
+/// The scale to render a page of the size at, lowering the resolution so that
+/// the page fits in [`MAX_PIXELS`].
+///
+/// Fails if the renderer could not create the pixmap, e.g. for a page too
+/// long to fit in pixels even at the lowered resolution.
+fn pixel_per_pt(size: Size, ppi: f32) -> Result<f32, String> {
+    let (width, height) = (size.x.to_pt() as f32, size.y.to_pt() as f32);
+    let mut pixel_per_pt = ppi / 72.0;
+    let pixels = width * pixel_per_pt * height * pixel_per_pt;
+    if pixels > MAX_PIXELS {
+        pixel_per_pt *= (MAX_PIXELS / pixels).sqrt();
+    }
+    // the size of the pixmap, like the renderer makes it
+    let pxw = (pixel_per_pt * width).round().max(1.0);
+    let pxh = (pixel_per_pt * height).round().max(1.0);
+    let fits = width.is_finite()
+        && height.is_finite()
+        && pxw * pxh <= MAX_PIXELS
+        && tiny_skia::IntSize::from_wh(pxw as u32, pxh as u32).is_some();
+    if !fits {
+        return Err(format!("cannot render a page of {width}pt x {height}pt"));
+    }
+    Ok(pixel_per_pt)
+}
+
+fn status_response(status: StatusCode, msg: &'static str) -> Response<Body> {
+    let mut res = Response::new(Body::from(msg));
+    *res.status_mut() = status;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::layout::Abs;
+
+    use super::*;
+
+    fn parse(uri: &str) -> Option<(usize, PageFormat)> {
+        let (path, query) = match uri.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (uri, None),
+        };
+        parse_page_request(path, query)
+    }
+
+    #[test]
+    fn test_parse_page_request() {
+        assert_eq!(parse("/page/1.svg"), Some((1, PageFormat::Svg)));
+        assert_eq!(
+            parse("/page/12.png"),
+            Some((12, PageFormat::Png { ppi: DEFAULT_PPI }))
+        );
+        assert_eq!(
+            parse("/page/3.png?v=2&ppi=72"),
+            Some((3, PageFormat::Png { ppi: 72. }))
+        );
+        assert_eq!(parse("/page/1.pdf"), None);
+        assert_eq!(parse("/pages/1.svg"), None);
+        assert_eq!(parse("/page/1"), None);
+    }
+
+    #[test]
+    fn test_parse_page_request_bad_page() {
+        assert_eq!(parse("/page/0.svg"), None);
+        assert_eq!(parse("/page/-1.svg"), None);
+        assert_eq!(parse("/page/one.svg"), None);
+        assert_eq!(parse("/page/1.5.svg"), None);
+        assert_eq!(parse("/page/.svg"), None);
+        assert_eq!(parse("/page/99999999999999999999999.svg"), None);
+    }
+
+    #[test]
+    fn test_pixel_per_pt() {
+        let a4 = Size::new(Abs::mm(210.), Abs::mm(297.));
+        assert_eq!(pixel_per_pt(a4, 144.), Ok(2.));
+        assert_eq!(pixel_per_pt(a4, MAX_PPI), Ok(MAX_PPI / 72.));
+    }
+
+    #[test]
+    fn test_pixel_per_pt_large_page() {
+        let size = Size::splat(Abs::cm(500.));
+        let scale = pixel_per_pt(size, MAX_PPI).unwrap();
+        assert!(scale < MAX_PPI / 72.);
+        let side = (size.x.to_pt() as f32 * scale).round();
+        assert!(side * side <= MAX_PIXELS);
+
+        // a page too long for a pixmap even at a low resolution
+        let size = Size::new(Abs::pt(0.01), Abs::cm(1e11));
+        assert!(pixel_per_pt(size, DEFAULT_PPI).is_err());
+        assert!(pixel_per_pt(Size::splat(Abs::inf()), DEFAULT_PPI).is_err());
+    }
+
+    #[test]
+    fn test_parse_page_request_ppi() {
+        assert_eq!(parse("/page/1.png?ppi=0"), None);
+        assert_eq!(parse("/page/1.png?ppi=-72"), None);
+        assert_eq!(parse("/page/1.png?ppi=NaN"), None);
+        assert_eq!(
+            parse("/page/1.png?ppi=100000"),
+            Some((1, PageFormat::Png { ppi: MAX_PPI }))
+        );
+        assert_eq!(
+            parse("/page/1.png?ppi=inf"),
+            Some((1, PageFormat::Png { ppi: MAX_PPI }))
+        );
+        assert_eq!(
+            parse("/page/1.png?ppi=0.5"),
+            Some((1, PageFormat::Png { ppi: 0.5 }))
+        );
+        // an unreadable resolution falls back to the default one
+        assert_eq!(
+            parse("/page/1.png?ppi=high"),
+            Some((1, PageFormat::Png { ppi: DEFAULT_PPI }))
+        );
+    }
+}